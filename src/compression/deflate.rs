@@ -1,58 +1,133 @@
 use std::io::{self, Read, Write};
-use std::time::SystemTime;
-use std::time::UNIX_EPOCH;
-use std::collections::HashMap;
-const GZIP_MAGIC: u16 = 0x8b1f;
-const COMPRESSION_METHOD_DEFLATE: u8 = 8;
-const FLAG_FNAME: u8 = 0x08;
-// Constants used for hashing, bit manipulation, etc.
-pub const HASH_BITS: usize = 15;
-pub const HASH_SIZE: usize = 1 << HASH_BITS;
-pub const HASH_MASK: usize = HASH_SIZE - 1;
-pub const TOO_FAR: usize = 4096;
+pub const GZIP_MAGIC: u16 = 0x8b1f;
+pub const COMPRESSION_METHOD_DEFLATE: u8 = 8;
+// Header flag bits (RFC 1952)
+pub const FLAG_FTEXT: u8 = 0x01;
+pub const FLAG_FHCRC: u8 = 0x02;
+pub const FLAG_FEXTRA: u8 = 0x04;
+pub const FLAG_FNAME: u8 = 0x08;
+pub const FLAG_FCOMMENT: u8 = 0x10;
+pub const FLAG_RESERVED: u8 = 0xE0;
+// Operating system codes for the OS header byte
+#[cfg(unix)]
+pub const OS_CODE: u8 = 3;
+#[cfg(windows)]
+pub const OS_CODE: u8 = 11;
+#[cfg(not(any(unix, windows)))]
+pub const OS_CODE: u8 = 255;
+pub const OS_UNKNOWN: u8 = 255;
 // const MIN_MATCH: usize = 3;
 pub const MIN_MATCH: usize = 3;
-pub const MAX_MATCH: usize = 256;
+pub const MAX_MATCH: usize = 258;
 pub const WSIZE: usize = 32_768; // Typical window size for gzip compression
-pub const MAX_DIST: usize = 32768; // Example value, adjust as needed
+// Minimum amount of lookahead, except at the end of the input file
+pub const MIN_LOOKAHEAD: usize = MAX_MATCH + MIN_MATCH + 1;
+// In order to simplify the code, particularly on 16 bit machines, match
+// distances are limited to MAX_DIST instead of WSIZE.
+pub const MAX_DIST: usize = WSIZE - MIN_LOOKAHEAD;
 
 use crate::compression::NIL;
 use crate::util::crc::updcrc;
-use crate::decompression::huft::build_huffman_tree;
-use crate::decompression::huft::huft_free;
-use crate::decompression::huft::generate_encoding_table;
+use crate::compression::trees::Trees;
 use crate::compression::lm_init::longest_match;
 use crate::compression::initialize_longest_match;
 use crate::compression::lm_init::DeflateState;
-fn update_hash(h: &mut usize, c: u8) {
-    const H_SHIFT: usize = 5; // H_SHIFT 的值通常取决于哈希函数的实现
-    *h = ((*h << H_SHIFT) ^ (c as usize)) & HASH_MASK;
-}
+use crate::compression::lm_init::init_hash;
+use crate::compression::lm_init::{update_hash, TOO_FAR};
 
 
+/// Inserts the string starting at `s` into the hash table and returns the
+/// previous head of its hash chain (NIL if there is none).
 fn insert_string(
     window: &[u8],
     s: usize,
     ins_h: &mut usize,
     prev: &mut [usize],
     head: &mut [usize]
-) -> Option<usize> {
-    if s + MIN_MATCH - 1 >= window.len() {
-        return None; // 防止越界访问
+) -> usize {
+    if s + MIN_MATCH > window.len() {
+        return NIL; // 防止越界访问
     }
-//     println!("ins_h: {:?}, windows:{:?}", ins_h,  window[s + MIN_MATCH - 1]);
-    update_hash(ins_h, window[s + MIN_MATCH - 1]);
+    *ins_h = update_hash(*ins_h, window[s + MIN_MATCH - 1]);
 
     let match_head = head[*ins_h];
     prev[s & (WSIZE - 1)] = match_head;
     head[*ins_h] = s;
-//     println!("head: {:?}", head[*ins_h]);
-    Some(match_head)
+    match_head
+}
+
+
+
+/// Metadata carried in a gzip member header.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GzHeader {
+    /// Original file name without any directory part (FNAME).
+    pub filename: Option<Vec<u8>>,
+    /// Modification time of the original file in seconds since the epoch,
+    /// or 0 when none is stored.
+    pub mtime: u32,
+    /// Operating system the member was created on.
+    pub os: u8,
+}
+
+impl Default for GzHeader {
+    fn default() -> Self {
+        GzHeader {
+            filename: None,
+            mtime: 0,
+            os: OS_CODE,
+        }
+    }
+}
+
+impl GzHeader {
+    /// A header that does not depend on the input file or the machine, so
+    /// that compressing the same data always yields the same bytes.
+    pub fn reproducible() -> Self {
+        GzHeader {
+            filename: None,
+            mtime: 0,
+            os: OS_UNKNOWN,
+        }
+    }
+}
+
+/// Compression level, from 1 (fastest) to 9 (best compression).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionLevel(u8);
+
+impl CompressionLevel {
+    pub const FAST: CompressionLevel = CompressionLevel(1);
+    pub const DEFAULT: CompressionLevel = CompressionLevel(6);
+    pub const BEST: CompressionLevel = CompressionLevel(9);
+
+    pub fn new(level: u8) -> Result<Self, &'static str> {
+        if !(1..=9).contains(&level) {
+            return Err("Invalid compression level");
+        }
+        Ok(CompressionLevel(level))
+    }
+
+    pub fn level(self) -> usize {
+        self.0 as usize
+    }
 }
 
+impl Default for CompressionLevel {
+    fn default() -> Self {
+        CompressionLevel::DEFAULT
+    }
+}
 
+/// Settings that control how `deflate` compresses its input.
+#[derive(Debug, Clone, Default)]
+pub struct DeflateOptions {
+    pub level: CompressionLevel,
+}
 
-pub fn write_gzip_header<W: Write + ?Sized>(output: &mut W, filename: Option<&str>) -> io::Result<()> {
+/// Writes a gzip member header; `xfl` carries the speed flags set by
+/// `initialize_longest_match`.
+pub fn write_gzip_header<W: Write + ?Sized>(output: &mut W, header: &GzHeader, xfl: u8) -> io::Result<()> {
     // Write the GZIP magic number
     output.write_all(&GZIP_MAGIC.to_le_bytes())?;
 
@@ -61,23 +136,21 @@ pub fn write_gzip_header<W: Write + ?Sized>(output: &mut W, filename: Option<&st
 
     // Write flags
     let mut flags: u8 = 0;
-    if filename.is_some() {
+    if header.filename.is_some() {
         flags |= FLAG_FNAME;
     }
     output.write_all(&[flags])?;
 
     // Write timestamp
-    let start = SystemTime::now();
-    let epoch = start.duration_since(UNIX_EPOCH).unwrap().as_secs() as u32;
-    output.write_all(&epoch.to_le_bytes())?;
+    output.write_all(&header.mtime.to_le_bytes())?;
 
     // Write extra flags (OS-specific)
-    output.write_all(&[0])?; // Extra flags
-    output.write_all(&[3])?; // OS (Unix)
+    output.write_all(&[xfl])?; // Extra flags
+    output.write_all(&[header.os])?; // OS
 
     // Write filename if provided
-    if let Some(name) = filename {
-        output.write_all(name.as_bytes())?;
+    if let Some(name) = &header.filename {
+        output.write_all(name)?;
         output.write_all(&[0])?; // Null terminator
     }
 
@@ -95,253 +168,633 @@ pub fn write_gzip_footer<W: Write + ?Sized>(output: &mut W, crc: u32, input_size
 }
 
 
-pub fn deflate(input: &mut dyn Read, output: &mut dyn Write, filename: Option<&str>) -> io::Result<usize> {
-     write_gzip_header(output, filename)?;
-    let max_bits = 15; // 设定Huffman编码允许的最大位数
-    let mut compressed_length: usize = 0;
-    let crc: u32 = 0;
-    let mut ins_h: usize = 0; // 初始化 ins_h 用于滚动哈希值
-    let mut buffer = [0u8; 8192];
-    let mut lookahead: usize;
-    let mut strstart = 0;
-    let mut hash_head = None;
-    let mut compressed_data = Vec::new();
+/// Compresses `input` into a single gzip member with the given header,
+/// returning the size of the deflate data.
+pub fn deflate(
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+    header: &GzHeader,
+    options: &DeflateOptions,
+) -> io::Result<usize> {
      // 设置压缩级别和标志位
-    let compression_level = 6;
+    let (mut state, mut trees, flags) = init_deflate(options)?;
+    write_gzip_header(output, header, flags as u8)?;
+
+    let mut input = CrcReader { inner: input, crc: 0, size: 0 };
+    deflate_stream(&mut state, &mut trees, options.level.level(), &mut input, output)?;
+    write_gzip_footer(output, input.crc, input.size as u32)?;
+
+    Ok(trees.compressed_len())
+}
+
+/// Compresses `input` into a bare deflate stream (RFC 1951), without any
+/// header or trailer, returning its size.
+pub fn deflate_raw(input: &mut dyn Read, output: &mut dyn Write, options: &DeflateOptions) -> io::Result<usize> {
+    let (mut state, mut trees, _) = init_deflate(options)?;
+    deflate_stream(&mut state, &mut trees, options.level.level(), input, output)?;
+    Ok(trees.compressed_len())
+}
+
+/// Sets up the matcher and the trees for `options`; also returns the
+/// speed flags, which go into the XFL byte of a gzip header.
+fn init_deflate(options: &DeflateOptions) -> io::Result<(DeflateState, Trees, u16)> {
     let mut flags: u16 = 0;
+    let mut state = DeflateState::new(2 * WSIZE);
+    initialize_longest_match(&mut state, options.level.level(), &mut flags)
+    .map_err(io::Error::other)?;
+    Ok((state, Trees::new(options.level.level()), flags))
+}
 
-    let mut state = DeflateState {
-        window: vec![0; 2 * WSIZE],
-        prev: vec![NIL; HASH_SIZE],
-        hash_chain: vec![NIL; HASH_SIZE],
-        strstart,
-        block_start: 0,
-        lookahead: 0,
-        max_chain_length: 0, // 你可以使用 initialize_longest_match 进行初始化
-    };
-//     println!("{}, {}", state.strstart, state.lookahead);
+/// Compresses all of `input`, up to and including the final block.
+fn deflate_stream(
+    state: &mut DeflateState,
+    trees: &mut Trees,
+    level: usize,
+    input: &mut dyn Read,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    loop {
+        refill_window(state, input)?;
+        deflate_window(state, trees, level, false, output)?;
+        if state.eofile && state.lookahead == 0 {
+            break;
+        }
+    }
 
-    initialize_longest_match(&mut state, compression_level, &mut flags)
-    .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-//     println!("{}, {}", state.strstart, state.lookahead);
+    // 刷新最后一块
+    flush_block(state, trees, output, true)
+}
 
-    // 假设你在这里计算符号频率（例如 lit_freqs 和 dist_freqs）
-    let mut lit_freqs = [0u16; 256];
-    let mut dist_freqs = [0u16; 30];
-    let mut block_data = Vec::new();
-//     let dist_freqs_u8: Vec<u8> = dist_freqs.iter().map(|&x| x as u8).collect();
+/// Runs the matcher for `level` over the bytes in the window.
+/// Unless `eofile` or `flush` is set, it stops as soon as fewer than
+/// MIN_LOOKAHEAD bytes are left, so that more input can be appended and
+/// the call repeated; the output does not depend on how the input was
+/// split. With `flush`, all the lookahead is processed as if the input
+/// ended there, but more input may follow.
+pub(crate) fn deflate_window(
+    state: &mut DeflateState,
+    trees: &mut Trees,
+    level: usize,
+    flush: bool,
+    output: &mut dyn Write,
+) -> io::Result<()> {
+    if state.strstart == state.dict_len {
+        // 尚未处理任何字节：用 strstart 处的字节初始化滚动哈希
+        init_hash(state);
+    }
+    // 与 gzip 相同，1 到 3 级使用不做惰性匹配的 deflate_fast
+    if level <= 3 {
+        deflate_fast(state, trees, flush, output)
+    } else {
+        deflate_slow(state, trees, flush, output)
+    }
+}
 
-    let lit_symbols: Vec<u16> = (0..lit_freqs.len() as u16).collect();
-    let dist_symbols: Vec<u16> = (0..dist_freqs.len() as u16).collect();
+/// Whether the matchers may process the byte at `strstart`: a longest match
+/// must always be able to look MIN_LOOKAHEAD bytes ahead, except at the end
+/// of the input or when flushing.
+fn can_advance(state: &DeflateState, flush: bool) -> bool {
+    state.lookahead != 0 && (state.eofile || flush || state.lookahead >= MIN_LOOKAHEAD)
+}
 
-        // 调用 build_huffman_tree 函数替代 huft_build
-//     let literal_tree =
-//             build_huffman_tree(&lit_freqs);
-//
-//     let distance_tree =
-//             build_huffman_tree(&dist_freqs);
-//
+/// Ends the current block (gzip's FLUSH_BLOCK): the symbols tallied since
+/// `block_start` are encoded and written to `output`, and a new block
+/// starts at `strstart`.
+pub(crate) fn flush_block(state: &mut DeflateState, trees: &mut Trees, output: &mut dyn Write, eof: bool) -> io::Result<()> {
+    // 块的原始数据仍在窗口中时才能作为 stored 块输出
+    let buf = if state.block_start >= 0 {
+        Some(&state.window[state.block_start as usize..state.strstart])
+    } else {
+        None
+    };
+    trees.flush_block(buf, eof);
+    state.block_start = state.strstart as isize;
+    trees.write_pending(output)?;
+    Ok(())
+}
 
-    loop {
+/// Passes the input through while keeping its CRC and length for the footer.
+struct CrcReader<'a> {
+    inner: &'a mut dyn Read,
+    crc: u32,
+    size: u64,
+}
 
-        fill_window(&mut state, input)?;
+impl Read for CrcReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.crc = updcrc(self.crc, &buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+}
 
-        if state.lookahead == state.strstart {
-            break;
+/// Greedy matching for the fast levels (gzip's deflate_fast): every match
+/// found is taken at once, and only short matches have their strings
+/// inserted into the hash table.
+/// Blocks are written to `output` as they fill up; the symbols of the final
+/// block are left in `trees` for the caller.
+fn deflate_fast(state: &mut DeflateState, trees: &mut Trees, flush: bool, output: &mut dyn Write) -> io::Result<()> {
+    // 快速模式下 max_lazy_match 表示插入哈希表的最大匹配长度
+    let max_insert_length = state.max_lazy_match;
+    state.prev_length = MIN_MATCH - 1;
+
+    while can_advance(state, flush) {
+        let mut match_length = 0;
+        let end_block;
+
+        let hash_head = insert_string(&state.window, state.strstart, &mut state.ins_h, &mut state.prev, &mut state.head);
+        if hash_head != NIL && state.strstart - hash_head <= MAX_DIST {
+            match_length = longest_match(hash_head, state);
         }
 
-        for &byte in &state.window[state.strstart..state.lookahead] {
-            hash_head = insert_string(&state.window, state.strstart, &mut ins_h, &mut state.prev, &mut state.hash_chain);
-            if let Some(hash) = hash_head {
-                let match_length = longest_match(hash, &state); // 获取最长匹配长度
-                let distance = state.strstart - hash;
-
-                if match_length >= MIN_MATCH {
-                    lit_freqs[match_length as usize] += 1;
-                    let (distance_code, extra_bits) = calculate_distance_code(distance);
-                    dist_freqs[distance_code as usize] += 1;
-                    block_data.push((match_length as u8, distance_code as u8, extra_bits));
-                } else {
-                    lit_freqs[byte as usize] = lit_freqs[byte as usize].saturating_add(1);
-                    block_data.push((byte, 0, 0)); // 字面符号没有对应的距离
+        if match_length >= MIN_MATCH {
+            end_block = trees.ct_tally(state.strstart - state.match_start, match_length - MIN_MATCH);
+            state.lookahead -= match_length;
+
+            if match_length <= max_insert_length {
+                // 短匹配：把匹配中剩余的字符串插入哈希表
+                for _ in 0..match_length - 1 {
+                    state.strstart += 1;
+                    insert_string(&state.window, state.strstart, &mut state.ins_h, &mut state.prev, &mut state.head);
+                }
+                state.strstart += 1;
+            } else {
+                // 长匹配：直接跳过，只根据新位置重新初始化滚动哈希
+                state.strstart += match_length;
+                if state.strstart + 1 < state.window.len() {
+                    state.ins_h = state.window[state.strstart] as usize;
+                    state.ins_h = update_hash(state.ins_h, state.window[state.strstart + 1]);
                 }
-            }else {
-                lit_freqs[byte as usize] = lit_freqs[byte as usize].saturating_add(1);
-                block_data.push((byte, 0, 0));
             }
+        } else {
+            // 没有匹配，输出字面量
+            end_block = trees.ct_tally(0, state.window[state.strstart] as usize);
+            state.lookahead -= 1;
             state.strstart += 1;
-            updcrc(crc, &[byte]);
         }
+        if end_block {
+            flush_block(state, trees, output, false)?;
+        }
+    }
 
-//         println!("{:?}, {:?}", &lit_freqs, &dist_freqs);
-        let literal_tree =
-                build_huffman_tree(&lit_freqs);
-
-        let distance_tree =
-                build_huffman_tree(&dist_freqs);
+    Ok(())
+}
 
+/// Lazy evaluation of matches (gzip's deflate_slow): a match is only taken
+/// if no better match is found at the next position; otherwise the current
+/// byte is emitted as a literal and the later match is used instead.
+/// Blocks are written to `output` as they fill up; the symbols of the final
+/// block are left in `trees` for the caller.
+fn deflate_slow(state: &mut DeflateState, trees: &mut Trees, flush: bool, output: &mut dyn Write) -> io::Result<()> {
+    let mut prev_match; // 前一个匹配的起始位置
 
-        let mut literal_encoding_table = HashMap::new();
-        generate_encoding_table(&literal_tree, vec![], &mut literal_encoding_table);
+    while can_advance(state, flush) {
+        let hash_head = insert_string(&state.window, state.strstart, &mut state.ins_h, &mut state.prev, &mut state.head);
 
-        let mut distance_encoding_table = HashMap::new();
-        generate_encoding_table(&distance_tree, vec![], &mut distance_encoding_table);
+        // 记录前一步的匹配，并在当前位置寻找新的匹配
+        state.prev_length = state.match_length;
+        prev_match = state.match_start;
+        state.match_length = MIN_MATCH - 1;
 
+        if hash_head != NIL && state.prev_length < state.max_lazy_match && state.strstart - hash_head <= MAX_DIST {
+            state.match_length = longest_match(hash_head, state);
 
-            // 对该区块的数据进行编码
-        for &(symbol, dist, extra_bits) in &block_data {
-            if dist == 0 {
-                if let Some(encoded_literal) = literal_encoding_table.get(&symbol) {
-                    compressed_data.extend(encoded_literal.clone());
-                }
-            } else {
-                if let Some(encoded_length) = literal_encoding_table.get(&symbol) {
-                    compressed_data.extend(encoded_length.clone());
-                }
+            // 距离太远的最短匹配不如直接输出字面量
+            if state.match_length == MIN_MATCH && state.strstart - state.match_start > TOO_FAR {
+                state.match_length -= 1;
+            }
+        }
 
-                if let Some(encoded_distance) = distance_encoding_table.get(&dist) {
-                    compressed_data.extend(encoded_distance.clone());
-                }
+        if state.prev_length >= MIN_MATCH && state.match_length <= state.prev_length {
+            // 前一个匹配不比当前的差，输出它
+            let end_block = trees.ct_tally(state.strstart - 1 - prev_match, state.prev_length - MIN_MATCH);
 
-                compressed_data.push(extra_bits);
+            // 把匹配中剩余的字符串插入哈希表（strstart-1 和 strstart 已经插入）
+            state.lookahead -= state.prev_length - 1;
+            for _ in 0..state.prev_length - 2 {
+                state.strstart += 1;
+                insert_string(&state.window, state.strstart, &mut state.ins_h, &mut state.prev, &mut state.head);
+            }
+            state.match_available = false;
+            state.match_length = MIN_MATCH - 1;
+            state.strstart += 1;
+            if end_block {
+                flush_block(state, trees, output, false)?;
+            }
+        } else if state.match_available {
+            // 当前位置有更长的匹配（或没有匹配），前一个字节作为字面量输出
+            if trees.ct_tally(0, state.window[state.strstart - 1] as usize) {
+                flush_block(state, trees, output, false)?;
             }
+            state.strstart += 1;
+            state.lookahead -= 1;
+        } else {
+            // 暂不输出，等待与下一个位置的匹配比较
+            state.match_available = true;
+            state.strstart += 1;
+            state.lookahead -= 1;
         }
+    }
+
+    if (state.eofile || flush) && state.lookahead == 0 && state.match_available {
+        trees.ct_tally(0, state.window[state.strstart - 1] as usize);
+        state.match_available = false;
+    }
 
-        lit_freqs.fill(0);
-        dist_freqs.fill(0);
-        block_data.clear();
+    Ok(())
+}
 
+/// Keeps at least MIN_LOOKAHEAD bytes ahead of `strstart` (so that a
+/// longest match can always be checked) until the input is exhausted.
+fn refill_window(state: &mut DeflateState, input: &mut dyn Read) -> io::Result<()> {
+    while state.lookahead < MIN_LOOKAHEAD && !state.eofile {
+        fill_window(state, input)?;
     }
+    Ok(())
+}
 
+/// Copies as much of `data` into the window as fits after the lookahead,
+/// sliding the window first if needed, and returns the number of bytes
+/// taken. This is the push counterpart of `fill_window`; it always takes
+/// at least one byte once `deflate_window` has consumed the lookahead.
+pub(crate) fn push_input(state: &mut DeflateState, data: &[u8]) -> usize {
+    let more = slide_window(state);
+    let start = state.strstart + state.lookahead;
+    let n = more.min(data.len());
+    state.window[start..start + n].copy_from_slice(&data[..n]);
+    state.lookahead += n;
+    n
+}
 
-    // 刷新最后一块
-    compressed_length += flush_block(output, true, &compressed_data)?;
-    write_gzip_footer(output, crc, state.strstart as u32)?;
-    println!("Total compressed length: {}", compressed_length);
-    // 释放哈夫曼树
-//     huft_free(literal_tree);
-//     huft_free(distance_tree);
-
-    Ok(compressed_length)
+/// Primes the window and the hash chains with a preset dictionary, so that
+/// the data that follows can refer to it as if it had been compressed just
+/// before. Only the last WSIZE bytes of `dictionary` are used. Must be
+/// called before any input is given.
+pub(crate) fn set_dictionary(state: &mut DeflateState, dictionary: &[u8]) {
+    assert!(state.strstart == state.dict_len && state.lookahead == 0, "dictionary set after input");
+    let dictionary = &dictionary[dictionary.len().saturating_sub(WSIZE)..];
+    let n = dictionary.len();
+    state.window[..n].copy_from_slice(dictionary);
+    state.head.fill(NIL);
+    state.prev.fill(NIL);
+
+    if n >= MIN_MATCH {
+        state.strstart = 0;
+        init_hash(state);
+        for s in 0..=n - MIN_MATCH {
+            insert_string(&state.window, s, &mut state.ins_h, &mut state.prev, &mut state.head);
+        }
+    }
+    state.strstart = n;
+    state.block_start = n as isize;
+    state.dict_len = n;
+}
+
+/// Marks the end of the input, after which the matchers run to the end of
+/// the lookahead.
+pub(crate) fn set_eof(state: &mut DeflateState) {
+    state.eofile = true;
+    // Don't let garbage pollute the dictionary
+    let start = state.strstart + state.lookahead;
+    let end = (start + MIN_MATCH - 1).min(state.window.len());
+    state.window[start..end].fill(0);
 }
 
+/// Fills the window when the lookahead becomes insufficient, sliding it
+/// first if needed. Sets `eofile` at the end of the input.
 fn fill_window(state: &mut DeflateState, input: &mut dyn Read) -> io::Result<()> {
-    // Check if we need to slide the window to avoid overflow
-//     println!("{}, {}", state.strstart, state.lookahead);
+    let more = slide_window(state);
+
+    if !state.eofile {
+        // Read new data after the current lookahead, never over it
+        let start = state.strstart + state.lookahead;
+        let read_amount = read_buf(input, &mut state.window[start..start + more])?;
+        if read_amount == 0 {
+            set_eof(state);
+        } else {
+            state.lookahead += read_amount;
+        }
+    }
+
+    Ok(())
+}
+
+/// Slides the upper half of the window down (together with the hash table
+/// entries) once `strstart` has moved past WSIZE + MAX_DIST, and returns the
+/// amount of free space at the end of the window.
+fn slide_window(state: &mut DeflateState) -> usize {
+    let mut more = state.window.len() - state.lookahead - state.strstart;
+
     if state.strstart >= WSIZE + MAX_DIST {
-        // Slide the window contents by WSIZE to the beginning
+        // Slide the window contents by WSIZE to the beginning; strstart
+        // stays at least MAX_DIST into the window so no match is lost
         state.window.copy_within(WSIZE..2 * WSIZE, 0);
-//         println!("{:?}", state.hash_chain);
-        // Update hash_chain and prev to reflect the shifted positions
-        for i in 0..HASH_SIZE {
-            state.hash_chain[i] = if state.hash_chain[i] >= WSIZE {
-                state.hash_chain[i] - WSIZE
-            } else {
-                NIL
-            };
+        state.match_start = state.match_start.saturating_sub(WSIZE);
+        state.strstart -= WSIZE;
+        state.block_start -= WSIZE as isize;
 
-            state.prev[i] = if state.prev[i] >= WSIZE {
-                state.prev[i] - WSIZE
-            } else {
-                NIL
-            };
+        // Update head and prev to reflect the shifted positions
+        let slide = |pos: &mut usize| {
+            *pos = if *pos != NIL && *pos >= WSIZE { *pos - WSIZE } else { NIL };
+        };
+        state.head.iter_mut().for_each(slide);
+        state.prev.iter_mut().for_each(slide);
+
+        more += WSIZE;
+    }
+
+    more
+}
+
+/// Reads until `buf` is full or the input is exhausted, so that the blocks
+/// produced never depend on how the reader happens to chunk its data.
+fn read_buf(input: &mut dyn Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut total = 0;
+    while total < buf.len() {
+        match input.read(&mut buf[total..]) {
+            Ok(0) => break,
+            Ok(n) => total += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
         }
+    }
+    Ok(total)
+}
 
-        // Adjust strstart and block_start to the new positions within the window
-        state.strstart -= WSIZE;
-        state.lookahead -= WSIZE;
-//         state.block_start -= WSIZE;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// A reader that hands out at most `chunk` bytes per call, like a pipe.
+    struct ChunkedReader<'a> {
+        data: &'a [u8],
+        chunk: usize,
     }
 
-    let remaining_space = std::cmp::min(WSIZE, 2 * WSIZE - state.strstart);
+    impl Read for ChunkedReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.chunk.min(buf.len()).min(self.data.len());
+            buf[..n].copy_from_slice(&self.data[..n]);
+            self.data = &self.data[n..];
+            Ok(n)
+        }
+    }
 
+    #[test]
+    fn test_output_independent_of_read_sizes() {
+        let data: Vec<u8> = (0..100_000u32).map(|i| (i * 7 % 251) as u8 ^ (i / 1000) as u8).collect();
+        let header = GzHeader::reproducible();
 
-    // Read more data into the window buffer if there’s space available
-    let read_amount = input.read(&mut state.window[state.strstart..state.strstart + remaining_space])?;
-    state.lookahead += read_amount;
+        let options = DeflateOptions::default();
 
+        let mut expected = Vec::new();
+        deflate(&mut Cursor::new(&data), &mut expected, &header, &options).unwrap();
 
-    Ok(())
-}
+        for chunk in [1, 1000, 4097] {
+            let mut output = Vec::new();
+            deflate(&mut ChunkedReader { data: &data, chunk }, &mut output, &header, &options).unwrap();
+            assert_eq!(output, expected, "read size {}", chunk);
+        }
+        assert_eq!(&expected[4..10], &[0, 0, 0, 0, 0, OS_UNKNOWN]);
+    }
 
+    #[test]
+    fn test_raw_stream_is_the_member_without_framing() {
+        let data = b"raw deflate, as found in zip entries and websocket frames. ".repeat(50);
+        let options = DeflateOptions::default();
+        let mut member = Vec::new();
+        let gz_len = deflate(&mut Cursor::new(&data), &mut member, &GzHeader::default(), &options).unwrap();
+        let mut raw = Vec::new();
+        let raw_len = deflate_raw(&mut Cursor::new(&data), &mut raw, &options).unwrap();
+
+        assert_eq!((raw_len, gz_len), (raw.len(), raw.len()));
+        assert_eq!(raw, &member[10..member.len() - 8]);
+        let mut output = Vec::new();
+        crate::decompression::inflate(&mut Cursor::new(&raw), &mut output).unwrap();
+        assert_eq!(output, data);
+    }
 
-fn flush_block(output: &mut dyn Write, eof: bool, compressed_data: &[u8]) -> io::Result<usize> {
-    let mut bytes_written = 0;
+    #[test]
+    fn test_every_level_round_trips() {
+        // Text with repeats, data with no matches, long runs, and enough of
+        // each to slide the window several times
+        let text: Vec<u8> = (0..20_000).flat_map(|i| format!("line {} of {}\n", i % 700, i % 13).into_bytes()).collect();
+        let mut seed = 1u32;
+        let noise: Vec<u8> = (0..100_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        let runs: Vec<u8> = (0..150_000u32).map(|i| (i / 3000) as u8).collect();
+
+        for data in [&text, &noise, &runs] {
+            for level in 1..=9 {
+                let options = DeflateOptions { level: CompressionLevel::new(level).unwrap() };
+                let mut compressed = Vec::new();
+                deflate(&mut Cursor::new(data), &mut compressed, &GzHeader::default(), &options).unwrap();
+                let mut output = Vec::new();
+                crate::decompression::GzDecoder::new(Cursor::new(&compressed))
+                    .unwrap()
+                    .read_to_end(&mut output)
+                    .unwrap();
+                assert!(output == *data, "level {}", level);
+            }
+        }
+    }
 
-    // 编码块头部信息
-    let block_header: u8 = if eof { 0b0000_0001 } else { 0b0000_0000 }; // 示例：最后一个块标志
-    output.write_all(&[block_header])?;
-    bytes_written += 1;
+    #[test]
+    fn test_xfl_reflects_level() {
+        for (level, xfl) in [(1, 4), (3, 0), (6, 0), (9, 2)] {
+            let options = DeflateOptions { level: CompressionLevel::new(level).unwrap() };
+            let mut output = Vec::new();
+            deflate(&mut Cursor::new(b"abcabcabc"), &mut output, &GzHeader::default(), &options).unwrap();
+            assert_eq!(output[8], xfl, "level {}", level);
+        }
+        assert!(CompressionLevel::new(0).is_err());
+        assert!(CompressionLevel::new(10).is_err());
+    }
 
-    // 写入实际压缩数据
-    output.write_all(compressed_data)?;
-    bytes_written += compressed_data.len();
+    #[test]
+    fn test_lazy_match_prefers_longer_next_match() {
+        let data = b"xybcdefghabczabcdefgh";
 
-    Ok(bytes_written)
-}
+        // "abc" at 13 would match 3 bytes, but "bcdefgh" at 14 matches 7:
+        // lazy evaluation emits 'a' as a literal and then the longer match.
+        let mut expected: Vec<(usize, usize)> = b"xybcdefghabcza".iter().map(|&b| (0, b as usize)).collect();
+        expected.push((12, 7 - MIN_MATCH));
+        assert_eq!(tally(6, data), expected);
+    }
 
-fn calculate_distance_code(distance: usize) -> (u8, u8) {
-    // 根据 `deflate` 算法的距离范围
-    if distance <= 1 {
-        return (0, 0);
-    } else if distance <= 2 {
-        return (1, 0);
-    } else if distance <= 3 {
-        return (2, 0);
-    } else if distance <= 4 {
-        return (3, 0);
-    } else if distance <= 6 {
-        return (4, (distance - 5) as u8);
-    } else if distance <= 8 {
-        return (5, (distance - 7) as u8);
-    } else if distance <= 12 {
-        return (6, (distance - 9) as u8);
-    } else if distance <= 16 {
-        return (7, (distance - 13) as u8);
-    } else if distance <= 24 {
-        return (8, (distance - 17) as u8);
-    } else if distance <= 32 {
-        return (9, (distance - 25) as u8);
-    } else if distance <= 48 {
-        return (10, (distance - 33) as u8);
-    } else if distance <= 64 {
-        return (11, (distance - 49) as u8);
-    } else if distance <= 96 {
-        return (12, (distance - 65) as u8);
-    } else if distance <= 128 {
-        return (13, (distance - 97) as u8);
-    } else if distance <= 192 {
-        return (14, (distance - 129) as u8);
-    } else if distance <= 256 {
-        return (15, (distance - 193) as u8);
-    } else if distance <= 384 {
-        return (16, (distance - 257) as u8);
-    } else if distance <= 512 {
-        return (17, (distance - 385) as u8);
-    } else if distance <= 768 {
-        return (18, (distance - 513) as u8);
-    } else if distance <= 1024 {
-        return (19, (distance - 769) as u8);
-    } else if distance <= 1536 {
-        return (20, (distance - 1025) as u8);
-    } else if distance <= 2048 {
-        return (21, (distance - 1537) as u8);
-    } else if distance <= 3072 {
-        return (22, (distance - 2049) as u8);
-    } else if distance <= 4096 {
-        return (23, (distance - 3073) as u8);
-    } else if distance <= 6144 {
-        return (24, (distance - 4097) as u8);
-    } else if distance <= 8192 {
-        return (25, (distance - 6145) as u8);
-    } else if distance <= 12288 {
-        return (26, (distance - 8193) as u8);
-    } else if distance <= 16384 {
-        return (27, (distance - 12289) as u8);
-    } else if distance <= 24576 {
-        return (28, (distance - 16385) as u8);
-    } else {
-        return (29, (distance - 24577) as u8);
+    #[test]
+    fn test_fast_match_is_greedy() {
+        let data = b"xybcdefghabczabcdefgh";
+
+        // Unlike the lazy test above, "abc" is taken as soon as it is found.
+        let mut expected: Vec<(usize, usize)> = b"xybcdefghabcz".iter().map(|&b| (0, b as usize)).collect();
+        expected.push((4, 3 - MIN_MATCH));
+        expected.push((12, 5 - MIN_MATCH));
+        assert_eq!(tally(1, data), expected);
+    }
+
+    #[test]
+    fn test_hash_chain_finds_older_longer_match() {
+        let data = b"abcdefabcXabcdef";
+
+        // The final "abcdef" must be matched against position 0 through the
+        // chain, not against the more recent "abcX" at the head.
+        let mut expected: Vec<(usize, usize)> = b"abcdef".iter().map(|&b| (0, b as usize)).collect();
+        expected.push((6, 3 - MIN_MATCH));
+        expected.push((0, b'X' as usize));
+        expected.push((10, 6 - MIN_MATCH));
+        assert_eq!(tally(9, data), expected);
+    }
+
+    #[test]
+    fn test_fill_window_slides_and_keeps_lookahead() {
+        let data: Vec<u8> = (0..5 * WSIZE + 1234).map(|i| (i % 251) as u8).collect();
+        let mut state = DeflateState::new(2 * WSIZE);
+        let mut input = Cursor::new(&data);
+        refill_window(&mut state, &mut input).unwrap();
+        assert_eq!(state.lookahead, 2 * WSIZE);
+
+        // Consume the input in uneven steps, refilling like the deflate loops
+        let mut consumed = 0;
+        let mut slides = 0;
+        while state.lookahead != 0 {
+            let step = state.lookahead.min(997);
+            state.strstart += step;
+            state.lookahead -= step;
+            consumed += step;
+
+            let before = state.strstart;
+            while state.lookahead < MIN_LOOKAHEAD && !state.eofile {
+                fill_window(&mut state, &mut input).unwrap();
+            }
+            if state.strstart != before {
+                assert_eq!(before - state.strstart, WSIZE);
+                assert!(state.strstart >= MAX_DIST);
+                slides += 1;
+            }
+
+            assert!(state.lookahead >= MIN_LOOKAHEAD || state.eofile);
+            let window = &state.window[state.strstart..state.strstart + state.lookahead];
+            assert_eq!(window, &data[consumed..consumed + state.lookahead]);
+        }
+        assert_eq!(consumed, data.len());
+        assert_eq!(slides, 4);
     }
-}
 
+    /// Records the size of every write.
+    struct WriteLog(Vec<usize>);
+
+    impl Write for WriteLog {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.push(buf.len());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_blocks_are_written_as_they_fill() {
+        // Many more symbols than LIT_BUFSIZE: several blocks must reach the
+        // writer before the final one, instead of being held in memory.
+        let mut seed = 7u32;
+        let data: Vec<u8> = (0..400_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                b'a' + (seed >> 16) as u8 % 16
+            })
+            .collect();
+        for level in [1, 6] {
+            let options = DeflateOptions { level: CompressionLevel::new(level).unwrap() };
+            let mut log = WriteLog(Vec::new());
+            deflate(&mut Cursor::new(&data), &mut log, &GzHeader::default(), &options).unwrap();
+            let blocks = log.0.iter().filter(|&&n| n > 8).count();
+            assert!(blocks >= 3, "level {}: {:?}", level, log.0);
+        }
+    }
+
+    #[test]
+    fn test_incompressible_input_falls_back_to_stored_blocks() {
+        let mut seed = 1u32;
+        let data: Vec<u8> = (0..100_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                (seed >> 16) as u8
+            })
+            .collect();
+        let mut output = Vec::new();
+        deflate(&mut Cursor::new(&data), &mut output, &GzHeader::default(), &DeflateOptions::default()).unwrap();
+        // 10 bytes of header, 8 of footer and 5 per stored block
+        assert!(output.len() <= data.len() + 18 + 5 * 8, "{} bytes", output.len());
+    }
+
+    /// Rebuilds the input from the symbols passed to ct_tally.
+    fn replay(symbols: &[(usize, usize)]) -> Vec<u8> {
+        let mut data = Vec::new();
+        for &(dist, lc) in symbols {
+            if dist == 0 {
+                data.push(lc as u8);
+            } else {
+                assert!(dist <= MAX_DIST, "distance {} too far", dist);
+                for _ in 0..lc + MIN_MATCH {
+                    data.push(data[data.len() - dist]);
+                }
+            }
+        }
+        data
+    }
+
+    fn tally(level: usize, data: &[u8]) -> Vec<(usize, usize)> {
+        let mut flags = 0;
+        let mut state = DeflateState::new(2 * WSIZE);
+        initialize_longest_match(&mut state, level, &mut flags).unwrap();
+        let mut trees = Trees::new(level);
+        let mut input = Cursor::new(data);
+        while !state.eofile || state.lookahead != 0 {
+            refill_window(&mut state, &mut input).unwrap();
+            deflate_window(&mut state, &mut trees, level, false, &mut io::sink()).unwrap();
+        }
+        trees.tallied_symbols()
+    }
+
+    #[test]
+    fn test_runs_use_max_match_and_stop_at_input_end() {
+        // Zero bytes are also what lies past the end of the input in the
+        // window, so any match running past the lookahead would show here.
+        for byte in [0u8, b'a'] {
+            for len in [1, 2, 3, 258, 259, 261, 1000] {
+                let data = vec![byte; len];
+                for level in [1, 6, 9] {
+                    let symbols = tally(level, &data);
+                    assert_eq!(replay(&symbols), data, "level {} length {}", level, len);
+                    if len >= 1000 {
+                        assert!(symbols.contains(&(1, MAX_MATCH - MIN_MATCH)));
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_runs_across_window_slides() {
+        let mut data: Vec<u8> = (0..2 * WSIZE - 300).map(|i| (i * 31 % 253) as u8).collect();
+        data.extend(std::iter::repeat_n(b'z', 1000));
+        data.extend((0..WSIZE).map(|i| (i % 7) as u8));
+        data.extend(std::iter::repeat_n(b'z', MAX_MATCH + 1));
+
+        for level in [1, 4, 9] {
+            let symbols = tally(level, &data);
+            assert_eq!(replay(&symbols), data, "level {}", level);
+            assert!(symbols.iter().all(|&(dist, lc)| dist == 0 || lc <= MAX_MATCH - MIN_MATCH));
+        }
+    }
+}
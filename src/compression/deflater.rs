@@ -0,0 +1,253 @@
+// src/compression/deflater.rs
+//
+// Incremental raw deflate over caller-owned buffers, with flush modes so
+// that interactive protocols can make everything sent so far decodable
+// by the peer without ending the stream.
+
+use std::io;
+
+use crate::compression::deflate::{
+    deflate_window, flush_block, push_input, set_dictionary, set_eof, DeflateOptions, WSIZE,
+};
+use crate::compression::lm_init::{initialize_longest_match, DeflateState, NIL};
+use crate::compression::trees::Trees;
+use crate::decompression::Status;
+
+/// What `Deflater::compress` should do once all the input is taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Flush {
+    /// Nothing: data may be held back to find better matches.
+    None,
+    /// Output everything so far and align on a byte boundary with an empty
+    /// stored block, so that the peer can decode all the data sent.
+    Sync,
+    /// Like `Sync`, and forget the history: what follows can be decoded
+    /// without the data before it.
+    Full,
+    /// Output everything and end the stream with a final block.
+    Finish,
+}
+
+/// Incremental raw deflate compressor (no gzip or zlib container).
+pub struct Deflater {
+    state: DeflateState,
+    trees: Trees,
+    level: usize,
+    pending: Vec<u8>,          // compressed output not yet returned
+    pending_pos: usize,        // first byte of `pending` not yet returned
+    last_flush: Option<Flush>, // flush done since the last input, if any
+}
+
+impl Deflater {
+    pub fn new(options: &DeflateOptions) -> Self {
+        let mut flags = 0;
+        let mut state = DeflateState::new(2 * WSIZE);
+        // CompressionLevel only holds valid levels
+        initialize_longest_match(&mut state, options.level.level(), &mut flags)
+            .expect("compression level out of range");
+        Deflater {
+            state,
+            trees: Trees::new(options.level.level()),
+            level: options.level.level(),
+            pending: Vec::new(),
+            pending_pos: 0,
+            last_flush: None,
+        }
+    }
+
+    /// Primes the compressor with a preset dictionary (up to 32K, only the
+    /// end of a longer one is used): the data can then refer to it, and
+    /// must be decompressed with `Inflater::set_dictionary` given the same
+    /// bytes. Fails once compression has started.
+    pub fn set_dictionary(&mut self, dictionary: &[u8]) -> io::Result<()> {
+        if self.state.strstart != self.state.dict_len || self.state.lookahead != 0 || self.state.eofile {
+            return Err(io::Error::other("dictionary set after compression started"));
+        }
+        set_dictionary(&mut self.state, dictionary);
+        Ok(())
+    }
+
+    /// Compresses from `input` into `output`, returning the number of input
+    /// bytes consumed, the number of output bytes produced, and why it
+    /// stopped: `Ok` when `output` is full (call again, with the same flush
+    /// mode, to get the rest), `NeedInput` once all the input has been
+    /// taken and the flush done, `StreamEnd` when a `Finish` has been
+    /// completely output. No input may be given after `Finish`.
+    pub fn compress(&mut self, input: &[u8], output: &mut [u8], flush: Flush) -> io::Result<(usize, usize, Status)> {
+        let mut consumed = 0;
+        let mut produced = self.drain(output);
+
+        // 输出缓冲区有空间时才接受更多输入，使待输出的数据不超过一块
+        while consumed < input.len() && produced < output.len() {
+            if self.state.eofile {
+                return Err(io::Error::other("input after the deflate stream was finished"));
+            }
+            let n = push_input(&mut self.state, &input[consumed..]);
+            consumed += n;
+            self.last_flush = None;
+            deflate_window(&mut self.state, &mut self.trees, self.level, false, &mut self.pending)?;
+            produced += self.drain(&mut output[produced..]);
+        }
+
+        if consumed == input.len() && flush != Flush::None && self.last_flush != Some(flush) {
+            self.flush(flush)?;
+            self.last_flush = Some(flush);
+            produced += self.drain(&mut output[produced..]);
+        }
+
+        let status = if self.pending_pos < self.pending.len() || consumed < input.len() {
+            Status::Ok
+        } else if self.last_flush == Some(Flush::Finish) {
+            Status::StreamEnd
+        } else {
+            Status::NeedInput
+        };
+        Ok((consumed, produced, status))
+    }
+
+    /// Processes all the lookahead and ends the current block as `flush`
+    /// requires.
+    fn flush(&mut self, flush: Flush) -> io::Result<()> {
+        if flush == Flush::Finish {
+            if !self.state.eofile {
+                set_eof(&mut self.state);
+                deflate_window(&mut self.state, &mut self.trees, self.level, false, &mut self.pending)?;
+                flush_block(&mut self.state, &mut self.trees, &mut self.pending, true)?;
+            }
+            return Ok(());
+        }
+
+        deflate_window(&mut self.state, &mut self.trees, self.level, true, &mut self.pending)?;
+        if self.trees.has_symbols() {
+            flush_block(&mut self.state, &mut self.trees, &mut self.pending, false)?;
+        }
+        self.trees.align_stored();
+        self.trees.write_pending(&mut self.pending)?;
+
+        if flush == Flush::Full {
+            // 清空哈希链，之后的匹配不会引用之前的数据
+            self.state.head.fill(NIL);
+            self.state.prev.fill(NIL);
+        }
+        Ok(())
+    }
+
+    /// Copies pending output into `output`, returning how much was copied.
+    fn drain(&mut self, output: &mut [u8]) -> usize {
+        let pending = &self.pending[self.pending_pos..];
+        let n = pending.len().min(output.len());
+        output[..n].copy_from_slice(&pending[..n]);
+        self.pending_pos += n;
+        if self.pending_pos == self.pending.len() {
+            self.pending.clear();
+            self.pending_pos = 0;
+        }
+        n
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decompression::Inflater;
+
+    /// Compresses `input` in one call with `flush`, using a small output
+    /// buffer, and returns everything produced.
+    fn compress_all(deflater: &mut Deflater, input: &[u8], flush: Flush) -> Vec<u8> {
+        let mut output = Vec::new();
+        let mut buf = [0u8; 100];
+        let mut pos = 0;
+        loop {
+            let (consumed, produced, status) = deflater.compress(&input[pos..], &mut buf, flush).unwrap();
+            pos += consumed;
+            output.extend_from_slice(&buf[..produced]);
+            if status != Status::Ok {
+                assert_eq!(pos, input.len());
+                return output;
+            }
+        }
+    }
+
+    fn inflate_all(inflater: &mut Inflater, input: &[u8]) -> (Vec<u8>, Status) {
+        let mut output = vec![0u8; 1 << 20];
+        let (consumed, produced, status) = inflater.decompress(input, &mut output).unwrap();
+        assert_eq!(consumed, input.len());
+        output.truncate(produced);
+        (output, status)
+    }
+
+    #[test]
+    fn test_sync_flush_makes_messages_decodable() {
+        let mut deflater = Deflater::new(&DeflateOptions::default());
+        let mut inflater = Inflater::new();
+        for i in 0..5 {
+            let message = format!("{{\"seq\":{},\"event\":\"tick\",\"payload\":\"{}\"}}", i, "x".repeat(i * 50));
+            let compressed = compress_all(&mut deflater, message.as_bytes(), Flush::Sync);
+            assert!(compressed.ends_with(&[0x00, 0x00, 0xff, 0xff]));
+            // Everything sent so far decodes, without waiting for more
+            let (decoded, status) = inflate_all(&mut inflater, &compressed);
+            assert_eq!(decoded, message.as_bytes());
+            assert_eq!(status, Status::NeedInput);
+        }
+
+        let compressed = compress_all(&mut deflater, b"bye", Flush::Finish);
+        let (decoded, status) = inflate_all(&mut inflater, &compressed);
+        assert_eq!(decoded, b"bye");
+        assert_eq!(status, Status::StreamEnd);
+    }
+
+    #[test]
+    fn test_full_flush_forgets_history() {
+        let message = b"the same message, sent twice over the wire".repeat(3);
+        let mut deflater = Deflater::new(&DeflateOptions::default());
+        compress_all(&mut deflater, &message, Flush::Full);
+        let second = compress_all(&mut deflater, &message, Flush::Full);
+
+        // A decoder starting at the full flush point needs no earlier data
+        let (decoded, status) = inflate_all(&mut Inflater::new(), &second);
+        assert_eq!(decoded, message);
+        assert_eq!(status, Status::NeedInput);
+    }
+
+    #[test]
+    fn test_preset_dictionary() {
+        let dictionary = br#"{"device":"","firmware":"","battery":,"temperature":,"status":"ok"}"#;
+        let message = br#"{"device":"sensor-17","firmware":"2.4.1","battery":87,"temperature":21.5,"status":"ok"}"#;
+
+        let mut plain = Deflater::new(&DeflateOptions::default());
+        let without = compress_all(&mut plain, message, Flush::Finish);
+        let mut primed = Deflater::new(&DeflateOptions::default());
+        primed.set_dictionary(dictionary).unwrap();
+        let with = compress_all(&mut primed, message, Flush::Finish);
+        assert!(with.len() + 20 < without.len(), "{} vs {}", with.len(), without.len());
+        assert!(primed.set_dictionary(dictionary).is_err());
+
+        let mut inflater = Inflater::new();
+        inflater.set_dictionary(dictionary).unwrap();
+        let (decoded, status) = inflate_all(&mut inflater, &with);
+        assert_eq!((&decoded[..], status), (&message[..], Status::StreamEnd));
+
+        // Without the dictionary the references reach before the start
+        let mut output = [0u8; 200];
+        assert!(Inflater::new().decompress(&with, &mut output).is_err());
+    }
+
+    #[test]
+    fn test_streaming_with_no_flush_and_finish() {
+        let data: Vec<u8> = (0..100_000u32).flat_map(|i| (i % 1777).to_le_bytes()).collect();
+        let mut deflater = Deflater::new(&DeflateOptions::default());
+        let mut compressed = Vec::new();
+        for chunk in data.chunks(4099) {
+            compressed.extend(compress_all(&mut deflater, chunk, Flush::None));
+        }
+        compressed.extend(compress_all(&mut deflater, &[], Flush::Finish));
+        // Further calls only report the end
+        assert_eq!(deflater.compress(&[], &mut [0u8; 10], Flush::Finish).unwrap(), (0, 0, Status::StreamEnd));
+
+        let mut inflater = Inflater::new();
+        let mut output = vec![0u8; data.len() + 1];
+        let (consumed, produced, status) = inflater.decompress(&compressed, &mut output).unwrap();
+        assert_eq!((consumed, status), (compressed.len(), Status::StreamEnd));
+        assert_eq!(&output[..produced], &data[..]);
+    }
+}
@@ -0,0 +1,323 @@
+// src/compression/encoder.rs
+//
+// Push-style gzip compression: data written to a `GzEncoder` is deflated
+// into the wrapped writer as it arrives, instead of being pulled from a
+// `Read` by `deflate()`. `GzEncoderReader` turns it around, compressing
+// the data of a reader as the compressed stream is read.
+
+use std::io::{self, Read, Write};
+
+use crate::compression::deflate::{
+    deflate_window, flush_block, push_input, set_eof, write_gzip_footer, write_gzip_header, DeflateOptions,
+    GzHeader, WSIZE,
+};
+use crate::compression::lm_init::{initialize_longest_match, DeflateState};
+use crate::compression::trees::Trees;
+use crate::util::crc::updcrc;
+
+/// A gzip compressor implementing `Write`: everything written to it is
+/// compressed into the inner writer. Call `finish` to write the last block
+/// and the gzip footer and get the inner writer back; dropping the encoder
+/// finishes the stream too, but ignores any error.
+pub struct GzEncoder<W: Write> {
+    inner: Option<W>,
+    header: Option<(GzHeader, u8)>, // header and XFL byte, until written
+    state: DeflateState,
+    trees: Trees,
+    level: usize,
+    crc: u32,
+    size: u64,
+    tail: Vec<u8>,  // last block and footer, until written
+    finished: bool, // whether the footer has been written
+}
+
+impl<W: Write> GzEncoder<W> {
+    /// Creates an encoder writing a gzip member with the given header to
+    /// `inner`. Nothing is written before the first write or `finish`.
+    pub fn new(inner: W, header: GzHeader, options: &DeflateOptions) -> Self {
+        let mut flags = 0;
+        let mut state = DeflateState::new(2 * WSIZE);
+        // CompressionLevel only holds valid levels
+        initialize_longest_match(&mut state, options.level.level(), &mut flags)
+            .expect("compression level out of range");
+        GzEncoder {
+            inner: Some(inner),
+            header: Some((header, flags as u8)),
+            state,
+            trees: Trees::new(options.level.level()),
+            level: options.level.level(),
+            crc: 0,
+            size: 0,
+            tail: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// The underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().expect("encoder already finished")
+    }
+
+    /// The underlying writer, mutably. Writing to it directly corrupts the
+    /// gzip stream.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.as_mut().expect("encoder already finished")
+    }
+
+    /// Completes the gzip member and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.inner.take().expect("encoder already finished"))
+    }
+
+    /// Writes the last block and the footer, keeping the encoder; does
+    /// nothing once it has succeeded, and may be called again after an
+    /// error. Nothing may be written afterwards.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.write_header()?;
+        if !self.state.eofile {
+            // 最后一块和尾部先编码到内存中，写入失败后可以重试
+            set_eof(&mut self.state);
+            deflate_window(&mut self.state, &mut self.trees, self.level, false, &mut self.tail)?;
+            flush_block(&mut self.state, &mut self.trees, &mut self.tail, true)?;
+            write_gzip_footer(&mut self.tail, self.crc, self.size as u32)?;
+        }
+        let output = self.inner.as_mut().expect("encoder already finished");
+        output.write_all(&self.tail)?;
+        self.tail.clear();
+        output.flush()?;
+        self.finished = true;
+        Ok(())
+    }
+
+    /// Writes the header if it has not been written yet; it is kept until
+    /// the write succeeds.
+    fn write_header(&mut self) -> io::Result<()> {
+        if let Some((header, xfl)) = &self.header {
+            let mut bytes = Vec::new();
+            write_gzip_header(&mut bytes, header, *xfl)?;
+            self.inner.as_mut().expect("encoder already finished").write_all(&bytes)?;
+            self.header = None;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for GzEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.state.eofile {
+            return Err(io::Error::other("write after the gzip stream was finished"));
+        }
+        self.write_header()?;
+        let output = self.inner.as_mut().expect("encoder already finished");
+
+        let mut rest = buf;
+        while !rest.is_empty() {
+            // 窗口中可用的数据都已处理过，总能再放入新的字节
+            let n = push_input(&mut self.state, rest);
+            self.crc = updcrc(self.crc, &rest[..n]);
+            self.size += n as u64;
+            rest = &rest[n..];
+            deflate_window(&mut self.state, &mut self.trees, self.level, false, output)?;
+        }
+        Ok(buf.len())
+    }
+
+    /// Flushes the inner writer. Data still held for the current block
+    /// stays in the encoder until the block is complete or `finish`.
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.as_mut().expect("encoder already finished").flush()
+    }
+}
+
+impl<W: Write> Drop for GzEncoder<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+/// A reader yielding the gzip compressed contents of another reader, which
+/// is read as the compressed data is requested.
+pub struct GzEncoderReader<R: Read> {
+    inner: R,
+    encoder: GzEncoder<Vec<u8>>,
+    pos: usize, // first byte of the encoder's output not yet returned
+    eof: bool,  // whether `inner` is exhausted and the encoder finished
+}
+
+impl<R: Read> GzEncoderReader<R> {
+    pub fn new(inner: R, header: GzHeader, options: &DeflateOptions) -> Self {
+        GzEncoderReader {
+            inner,
+            encoder: GzEncoder::new(Vec::new(), header, options),
+            pos: 0,
+            eof: false,
+        }
+    }
+
+    /// The underlying reader.
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Returns the underlying reader; compressed data not read yet is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R: Read> Read for GzEncoderReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut chunk = [0u8; 8192];
+        loop {
+            let pending = &self.encoder.get_ref()[self.pos..];
+            if !pending.is_empty() {
+                let n = pending.len().min(buf.len());
+                buf[..n].copy_from_slice(&pending[..n]);
+                self.pos += n;
+                if self.pos == self.encoder.get_ref().len() {
+                    self.encoder.get_mut().clear();
+                    self.pos = 0;
+                }
+                return Ok(n);
+            }
+            if self.eof || buf.is_empty() {
+                return Ok(0);
+            }
+
+            // 读入更多原始数据，直到产生压缩输出
+            let n = self.inner.read(&mut chunk)?;
+            if n == 0 {
+                self.encoder.try_finish()?;
+                self.eof = true;
+            } else {
+                self.encoder.write_all(&chunk[..n])?;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::deflate::deflate;
+    use crate::compression::CompressionLevel;
+    use std::io::Cursor;
+
+    fn sample() -> Vec<u8> {
+        let mut data = Vec::new();
+        for i in 0..6_000u32 {
+            data.extend_from_slice(format!("line {} value {}\n", i % 977, i.wrapping_mul(2_654_435_761) >> 20).as_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn test_encoder_matches_deflate_for_any_write_sizes() {
+        let data = sample();
+        for level in [1, 6, 9] {
+            let options = DeflateOptions { level: CompressionLevel::new(level).unwrap() };
+            let header = GzHeader { filename: Some(b"log.txt".to_vec()), mtime: 1234, ..GzHeader::default() };
+            let mut expected = Vec::new();
+            deflate(&mut Cursor::new(&data), &mut expected, &header, &options).unwrap();
+
+            for chunk in [1, 7, 300, 4096, data.len()] {
+                let mut encoder = GzEncoder::new(Vec::new(), header.clone(), &options);
+                for part in data.chunks(chunk) {
+                    encoder.write_all(part).unwrap();
+                }
+                assert_eq!(encoder.finish().unwrap(), expected, "level {} chunk {}", level, chunk);
+            }
+        }
+    }
+
+    #[test]
+    fn test_empty_stream_and_drop_finish() {
+        let mut expected = Vec::new();
+        deflate(&mut Cursor::new(b""), &mut expected, &GzHeader::default(), &DeflateOptions::default()).unwrap();
+        let encoder = GzEncoder::new(Vec::new(), GzHeader::default(), &DeflateOptions::default());
+        assert_eq!(encoder.finish().unwrap(), expected);
+
+        let mut output = Vec::new();
+        {
+            let mut encoder = GzEncoder::new(&mut output, GzHeader::default(), &DeflateOptions::default());
+            encoder.write_all(b"dropped").unwrap();
+        }
+        let mut expected = Vec::new();
+        deflate(&mut Cursor::new(b"dropped"), &mut expected, &GzHeader::default(), &DeflateOptions::default()).unwrap();
+        assert_eq!(output, expected);
+    }
+
+    /// A writer whose write number `fail_at` fails without writing
+    /// anything, like a disk that is full for a moment.
+    struct FailOnce {
+        out: Vec<u8>,
+        calls: usize,
+        fail_at: usize,
+    }
+
+    impl Write for FailOnce {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            if self.calls - 1 == self.fail_at {
+                return Err(io::Error::other("no space left"));
+            }
+            self.out.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_finish_can_be_retried_after_a_write_error() {
+        let data = sample();
+        let header = GzHeader { filename: Some(b"log.txt".to_vec()), ..GzHeader::default() };
+        let mut expected = Vec::new();
+        deflate(&mut Cursor::new(&data), &mut expected, &header, &DeflateOptions::default()).unwrap();
+
+        // The header write fails
+        let writer = FailOnce { out: Vec::new(), calls: 0, fail_at: 0 };
+        let mut encoder = GzEncoder::new(writer, header.clone(), &DeflateOptions::default());
+        assert!(encoder.write_all(&data).is_err());
+        encoder.write_all(&data).unwrap();
+        assert_eq!(encoder.finish().unwrap().out, expected);
+
+        // The write of the last block and footer fails
+        let writer = FailOnce { out: Vec::new(), calls: 0, fail_at: usize::MAX };
+        let mut encoder = GzEncoder::new(writer, header.clone(), &DeflateOptions::default());
+        encoder.write_all(&data).unwrap();
+        encoder.get_mut().fail_at = encoder.get_ref().calls;
+        assert!(encoder.try_finish().is_err());
+        encoder.try_finish().unwrap();
+        assert_eq!(encoder.finish().unwrap().out, expected);
+    }
+
+    #[test]
+    fn test_encoder_reader_yields_deflate_output() {
+        let data = sample();
+        let options = DeflateOptions { level: CompressionLevel::new(9).unwrap() };
+        let mut expected = Vec::new();
+        deflate(&mut Cursor::new(&data), &mut expected, &GzHeader::default(), &options).unwrap();
+
+        let mut reader = GzEncoderReader::new(Cursor::new(&data), GzHeader::default(), &options);
+        let mut output = Vec::new();
+        let mut small = [0u8; 100];
+        loop {
+            let n = reader.read(&mut small).unwrap();
+            if n == 0 {
+                break;
+            }
+            output.extend_from_slice(&small[..n]);
+        }
+        assert_eq!(output, expected);
+        assert_eq!(reader.read(&mut small).unwrap(), 0);
+    }
+}
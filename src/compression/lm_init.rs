@@ -1,65 +1,86 @@
 use crate::compression::MIN_MATCH;
 use crate::compression::MAX_MATCH;
 // Constants related to hash size, window size, etc.
-const HASH_BITS: usize = 15;
-const HASH_SIZE: usize = 1 << HASH_BITS;
-const HASH_MASK: usize = HASH_SIZE - 1;
-pub const NIL: usize = 0;
+pub(crate) const HASH_BITS: usize = 15;
+pub(crate) const HASH_SIZE: usize = 1 << HASH_BITS;
+pub(crate) const HASH_MASK: usize = HASH_SIZE - 1;
+// 哈希链的结束标记；不能用 0，因为位置 0 本身也是合法的匹配位置
+pub const NIL: usize = usize::MAX;
 
-const TOO_FAR: usize = 4096;
+pub(crate) const TOO_FAR: usize = 4096;
 
-// Speed flags
-const FAST: u16 = 4;
-const SLOW: u16 = 2;
+// Speed flags, stored in the XFL byte of the gzip header
+pub const FAST: u16 = 4;
+pub const SLOW: u16 = 2;
 use crate::compression::deflate::MAX_DIST;
 use crate::compression::deflate::WSIZE;
-// Configuration table for compression levels
+// Configuration table for compression levels (the values of gzip's deflate.c)
 struct Config {
-    max_lazy: usize,
-    good_length: usize,
-    nice_length: usize,
+    good_length: usize, // reduce lazy search above this match length
+    max_lazy: usize,    // do not perform lazy search above this match length
+    nice_length: usize, // quit search above this match length
     max_chain: usize,
 }
 
 const CONFIGURATION_TABLE: [Config; 10] = [
-    Config { max_lazy: 0, good_length: 0, nice_length: 0, max_chain: 0 },
-    Config { max_lazy: 4, good_length: 4, nice_length: 8, max_chain: 4 },
-    Config { max_lazy: 4, good_length: 5, nice_length: 16, max_chain: 8 },
-    Config { max_lazy: 4, good_length: 6, nice_length: 32, max_chain: 32 },
-    Config { max_lazy: 4, good_length: 4, nice_length: 16, max_chain: 16 },
-    Config { max_lazy: 8, good_length: 16, nice_length: 32, max_chain: 32 },
-    Config { max_lazy: 8, good_length: 16, nice_length: 128, max_chain: 128 },
-    Config { max_lazy: 8, good_length: 32, nice_length: 128, max_chain: 256 },
-    Config { max_lazy: 32, good_length: 128, nice_length: 258, max_chain: 1024 },
-    Config { max_lazy: 32, good_length: 258, nice_length: 258, max_chain: 4096 },
+    Config { good_length: 0, max_lazy: 0, nice_length: 0, max_chain: 0 }, // store only
+    Config { good_length: 4, max_lazy: 4, nice_length: 8, max_chain: 4 }, // maximum speed, no lazy matches
+    Config { good_length: 4, max_lazy: 5, nice_length: 16, max_chain: 8 },
+    Config { good_length: 4, max_lazy: 6, nice_length: 32, max_chain: 32 },
+    Config { good_length: 4, max_lazy: 4, nice_length: 16, max_chain: 16 }, // lazy matches
+    Config { good_length: 8, max_lazy: 16, nice_length: 32, max_chain: 32 },
+    Config { good_length: 8, max_lazy: 16, nice_length: 128, max_chain: 128 },
+    Config { good_length: 8, max_lazy: 32, nice_length: 128, max_chain: 256 },
+    Config { good_length: 32, max_lazy: 128, nice_length: 258, max_chain: 1024 },
+    Config { good_length: 32, max_lazy: 258, nice_length: 258, max_chain: 4096 }, // maximum compression
 ];
 
 pub struct DeflateState {
     pub window: Vec<u8>,       // 滑动窗口缓冲区
-    pub hash_chain: Vec<usize>, // 匹配的哈希链
-    pub prev: Vec<usize>,      // 用于管理链的前一个元素
+    pub head: Vec<usize>,      // 每个哈希值最近一次出现的位置
+    pub prev: Vec<usize>,      // prev[pos & WMASK]：同一哈希值的前一个位置，构成哈希链
+    pub ins_h: usize,          // 待插入字符串的滚动哈希值
     pub strstart: usize,       // 字符串的起始位置
-    pub block_start: usize,    // 当前块的起始位置
-    pub lookahead: usize,      // 前向查看数据量
+    pub block_start: isize,    // 当前块的起始位置；窗口滑动后可能为负
+    pub lookahead: usize,      // strstart 之后窗口中的有效字节数
+    pub eofile: bool,          // 输入是否已经读完
     pub max_chain_length: usize, // 最大链长度
+    pub max_lazy_match: usize, // 超过此长度不再进行惰性匹配
+    pub good_match: usize,     // 前一个匹配达到此长度时缩短搜索链
+    pub nice_match: usize,     // 找到此长度的匹配即停止搜索
+    pub prev_length: usize,    // 前一步找到的最佳匹配长度
+    pub match_start: usize,    // 当前匹配的起始位置
+    pub match_length: usize,   // 当前位置的最佳匹配长度（惰性匹配）
+    pub match_available: bool, // 前一个位置的字节是否还未输出（惰性匹配）
+    pub dict_len: usize,       // 预设字典的长度；窗口开头的这些字节只作为匹配的历史
 }
 
 impl DeflateState {
     pub fn new(window_size: usize) -> Self {
         DeflateState {
             window: vec![0; window_size],
-            hash_chain: vec![NIL; HASH_SIZE],
+            head: vec![NIL; HASH_SIZE],
             prev: vec![NIL; WSIZE],
+            ins_h: 0,
             strstart: 0,
             block_start: 0,
             lookahead: 0,
+            eofile: false,
             max_chain_length: 0,
+            max_lazy_match: 0,
+            good_match: 0,
+            nice_match: 0,
+            prev_length: MIN_MATCH - 1,
+            match_start: 0,
+            match_length: MIN_MATCH - 1,
+            match_available: false,
+            dict_len: 0,
         }
     }
 }
 /// Initializes the longest match settings for the deflate algorithm.
 pub fn initialize_longest_match(state: &mut DeflateState, compression_level: usize, flags: &mut u16) -> Result<(), &'static str> {
-    if compression_level < 1 || compression_level > 9 {
+    if !(1..=9).contains(&compression_level) {
         return Err("Invalid compression level");
     }
 
@@ -67,9 +88,9 @@ pub fn initialize_longest_match(state: &mut DeflateState, compression_level: usi
 
     // 将配置参数应用到 `state` 中
     state.max_chain_length = config.max_chain;
-//     state.good_match = config.good_length;
-//     state.nice_match = config.nice_length;
-//     state.max_lazy_match = config.max_lazy;
+    state.good_match = config.good_length;
+    state.nice_match = config.nice_length;
+    state.max_lazy_match = config.max_lazy;
 
     // 设置标志位
     if compression_level == 1 {
@@ -78,73 +99,78 @@ pub fn initialize_longest_match(state: &mut DeflateState, compression_level: usi
         *flags |= SLOW;
     }
 
-//     // 通过读取滑动窗口的数据设置 `lookahead` 的初始值
-//     state.lookahead = read_into_window(&mut state.window)?;
+    Ok(())
+}
 
-    // 准备初始哈希值
-    let mut ins_h = 0;
+/// 用 strstart 处的 MIN_MATCH-1 个字节初始化滚动哈希，窗口读入初始数据后调用
+pub fn init_hash(state: &mut DeflateState) {
+    state.ins_h = 0;
     for j in 0..(MIN_MATCH - 1) {
-        ins_h = update_hash(ins_h, state.window[j]);
-        println!("ins_h:{:?}, state.window[j]:{:?}", ins_h, state.window[j]);
+        state.ins_h = update_hash(state.ins_h, state.window[state.strstart + j]);
     }
-
-    Ok(())
 }
 
 
-pub fn longest_match(cur_match: usize, state: &DeflateState) -> usize {
-    let mut best_len = MIN_MATCH - 1;
+/// Walks the hash chain starting at `cur_match` looking for the longest
+/// match for the string at `strstart`. Only matches longer than
+/// `prev_length` are considered; the start of the best one is stored in
+/// `match_start` and its length, at most MAX_MATCH and never past the
+/// lookahead, returned.
+pub fn longest_match(cur_match: usize, state: &mut DeflateState) -> usize {
     let mut chain_length = state.max_chain_length;
     let strstart = state.strstart;
     let window = &state.window;
+    let mut best_len = state.prev_length;
     let mut match_pos = cur_match;
-//     println!("window: {:?}", window);
-//     if match_pos>0{
-//         println!("match_pos: {:?}, strstart: {:?}", match_pos, strstart);
-//     }
-    while chain_length > 0 && match_pos > 0 && (strstart - match_pos ) <= MAX_DIST {
-        let mut match_len = 0;
-//         println!("{:?}", &state.window);
-        // 比较窗口中的字节
-        while match_len < WSIZE
-            && match_len < window.len() - strstart
-            && match_len < window.len() - match_pos
-            && window[strstart + match_len] == window[match_pos + match_len]
-        {
-//             println!("{:?}: {:?}: {:?}", strstart, match_pos, match_len);
-//             println!("{:?}: {:?}", window[strstart + match_len], window[match_pos + match_len]);
-            match_len += 1;
-            if match_len >= MAX_MATCH-1 {
-                break;
-            }
+
+    // 前一个匹配已经足够好时，只搜索四分之一的哈希链
+    if state.prev_length >= state.good_match {
+        chain_length >>= 2;
+    }
+
+    // 匹配不能超出窗口中的有效数据
+    let max_len = MAX_MATCH.min(state.lookahead);
+
+    while match_pos != NIL && match_pos < strstart && strstart - match_pos <= MAX_DIST {
+        if best_len >= max_len {
+            break;
         }
 
-        // 更新 best_len 以存储当前找到的最长匹配
-        if match_len > best_len {
-            best_len = match_len;
+        // 先比较当前最佳长度处的字节，不可能更长的匹配直接跳过
+        if window[match_pos + best_len] == window[strstart + best_len] {
+            let mut match_len = 0;
+            while match_len < max_len && window[strstart + match_len] == window[match_pos + match_len] {
+                match_len += 1;
+            }
+
+            // 更新 best_len 以存储当前找到的最长匹配
+            if match_len > best_len {
+                state.match_start = match_pos;
+                best_len = match_len;
+                // 已经足够长，不必再沿链查找
+                if match_len >= state.nice_match {
+                    break;
+                }
+            }
         }
 
         chain_length -= 1;
+        if chain_length == 0 {
+            break;
+        }
 
-        // 获取链中的下一个匹配位置
-        match_pos = state.hash_chain[match_pos % WSIZE];
+        // 沿哈希链取同一哈希值的前一个位置
+        match_pos = state.prev[match_pos & (WSIZE - 1)];
     }
 
-    best_len
+    best_len.min(state.lookahead)
 }
 
 
 
 
 /// Updates the hash based on input byte
-fn update_hash(h: usize, c: u8) -> usize {
+pub(crate) fn update_hash(h: usize, c: u8) -> usize {
     const H_SHIFT: usize = 5;
     ((h << H_SHIFT) ^ (c as usize)) & HASH_MASK
 }
-
-/// Reads data into the sliding window buffer
-/// This is a placeholder function representing the actual data reading process.
-fn read_into_window(window: &mut [u8]) -> Result<usize, &'static str> {
-    // Placeholder for reading data and returning the number of bytes read
-    Ok(window.len())  // Replace with actual amount read
-}
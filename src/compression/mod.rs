@@ -1,10 +1,18 @@
 // src/compression/mod.rs
 
 pub mod deflate;
+pub mod deflater;
+pub mod encoder;
 pub mod lm_init;
+pub mod trees;
 pub mod utils;
+pub mod zlib;
 
-pub use deflate::deflate;
+pub use deflate::{deflate, deflate_raw};
+pub use deflate::{CompressionLevel, DeflateOptions, GzHeader};
+pub use deflater::{Deflater, Flush};
+pub use encoder::{GzEncoder, GzEncoderReader};
+pub use zlib::{ZlibEncoder, ZlibHeader};
 pub use deflate::MIN_MATCH;
 pub use deflate::MAX_MATCH;
 pub use lm_init::initialize_longest_match;
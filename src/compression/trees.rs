@@ -0,0 +1,837 @@
+// src/compression/trees.rs
+//
+// Output of deflate blocks, following gzip's trees.c: the literals and
+// matches found by the matcher are collected with `ct_tally` and then
+// encoded by `flush_block`, as a stored block or with the static or
+// dynamic Huffman trees, whichever is smallest.
+
+use std::io::{self, Write};
+
+use crate::compression::deflate::MIN_MATCH;
+
+const MAX_BITS: usize = 15; // all codes must not exceed MAX_BITS bits
+const MAX_BL_BITS: usize = 7; // bit length codes must not exceed MAX_BL_BITS bits
+
+const LENGTH_CODES: usize = 29; // number of length codes, not counting the special END_BLOCK code
+const LITERALS: usize = 256; // number of literal bytes 0..255
+const END_BLOCK: usize = 256; // end of block literal code
+const L_CODES: usize = LITERALS + 1 + LENGTH_CODES; // number of Literal or Length codes, including END_BLOCK
+const D_CODES: usize = 30; // number of distance codes
+const BL_CODES: usize = 19; // number of codes used to transfer the bit lengths
+const HEAP_SIZE: usize = 2 * L_CODES + 1; // maximum heap size
+
+// Sizes of the symbol buffers; a block is flushed when either is full
+pub const LIT_BUFSIZE: usize = 0x8000;
+pub const DIST_BUFSIZE: usize = LIT_BUFSIZE;
+
+const STORED_BLOCK: u32 = 0;
+const STATIC_TREES: u32 = 1;
+const DYN_TREES: u32 = 2;
+
+const REP_3_6: usize = 16; // repeat previous bit length 3-6 times (2 bits of repeat count)
+const REPZ_3_10: usize = 17; // repeat a zero length 3-10 times (3 bits of repeat count)
+const REPZ_11_138: usize = 18; // repeat a zero length 11-138 times (7 bits of repeat count)
+
+// extra bits for each length code
+const EXTRA_LBITS: [u32; LENGTH_CODES] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+
+// extra bits for each distance code
+const EXTRA_DBITS: [u32; D_CODES] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13, 13,
+];
+
+// extra bits for each bit length code
+const EXTRA_BLBITS: [u32; BL_CODES] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 3, 7];
+
+// The lengths of the bit length codes are sent in order of decreasing
+// probability, to avoid transmitting the lengths for unused bit length codes.
+const BL_ORDER: [usize; BL_CODES] = [16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15];
+
+/// A node of a Huffman tree: the leaves are the symbols, followed by the
+/// internal nodes created by `build_tree`.
+#[derive(Clone, Copy, Default)]
+struct CtData {
+    freq: u32, // frequency count
+    code: u16, // bit string
+    dad: u16,  // father node in the Huffman tree
+    len: u16,  // length of the bit string
+}
+
+struct StaticTables {
+    length_code: [u8; 256], // length code for each normalized match length (0 == MIN_MATCH)
+    dist_code: [u8; 512],   // distance codes, see `d_code`
+    base_length: [u16; LENGTH_CODES],
+    base_dist: [u16; D_CODES],
+    static_ltree: [CtData; L_CODES + 2],
+    static_dtree: [CtData; D_CODES],
+}
+
+lazy_static::lazy_static! {
+    static ref TABLES: StaticTables = {
+        let mut tables = StaticTables {
+            length_code: [0; 256],
+            dist_code: [0; 512],
+            base_length: [0; LENGTH_CODES],
+            base_dist: [0; D_CODES],
+            static_ltree: [CtData::default(); L_CODES + 2],
+            static_dtree: [CtData::default(); D_CODES],
+        };
+
+        // Initialize the mapping length (0..255) -> length code (0..28)
+        let mut length = 0;
+        for (code, &extra) in EXTRA_LBITS[..LENGTH_CODES - 1].iter().enumerate() {
+            tables.base_length[code] = length as u16;
+            for _ in 0..(1 << extra) {
+                tables.length_code[length] = code as u8;
+                length += 1;
+            }
+        }
+        // The length 255 (match length 258) can be represented in two
+        // different ways: code 284 + 5 bits or code 285, so we overwrite
+        // length_code[255] to use the best encoding.
+        tables.length_code[length - 1] = (LENGTH_CODES - 1) as u8;
+
+        // Initialize the mapping dist (0..32K) -> dist code (0..29)
+        let mut dist = 0;
+        for (code, &extra) in EXTRA_DBITS[..16].iter().enumerate() {
+            tables.base_dist[code] = dist as u16;
+            for _ in 0..(1 << extra) {
+                tables.dist_code[dist] = code as u8;
+                dist += 1;
+            }
+        }
+        dist >>= 7; // from now on, all distances are divided by 128
+        for (code, &extra) in EXTRA_DBITS.iter().enumerate().skip(16) {
+            tables.base_dist[code] = (dist << 7) as u16;
+            for _ in 0..(1 << (extra - 7)) {
+                tables.dist_code[256 + dist] = code as u8;
+                dist += 1;
+            }
+        }
+
+        // Construct the codes of the static literal tree
+        let mut bl_count = [0u16; 16];
+        for (n, node) in tables.static_ltree.iter_mut().enumerate() {
+            node.len = match n {
+                0..=143 => 8,
+                144..=255 => 9,
+                256..=279 => 7,
+                _ => 8,
+            };
+            bl_count[node.len as usize] += 1;
+        }
+        gen_codes(&mut tables.static_ltree, &bl_count);
+
+        // The static distance tree is trivial
+        for (n, node) in tables.static_dtree.iter_mut().enumerate() {
+            node.len = 5;
+            node.code = bi_reverse(n as u32, 5) as u16;
+        }
+
+        tables
+    };
+}
+
+/// Mapping from a distance to a distance code. `dist` is the distance - 1.
+fn d_code(dist: usize) -> usize {
+    if dist < 256 {
+        TABLES.dist_code[dist] as usize
+    } else {
+        TABLES.dist_code[256 + (dist >> 7)] as usize
+    }
+}
+
+/// Generates the codes for a tree whose bit lengths are already set, given
+/// the number of codes at each bit length.
+fn gen_codes(tree: &mut [CtData], bl_count: &[u16; 16]) {
+    let mut next_code = [0u32; 16];
+    let mut code = 0u32;
+    for bits in 1..16 {
+        code = (code + bl_count[bits - 1] as u32) << 1;
+        next_code[bits] = code;
+    }
+
+    for node in tree.iter_mut() {
+        let len = node.len as usize;
+        if len == 0 {
+            continue;
+        }
+        // Now reverse the bits, deflate sends codes starting with the LSB
+        node.code = bi_reverse(next_code[len], len as u32) as u16;
+        next_code[len] += 1;
+    }
+}
+
+/// Reverses the first `len` bits of a code.
+fn bi_reverse(code: u32, len: u32) -> u32 {
+    code.reverse_bits() >> (32 - len)
+}
+
+/// Description of one of the three trees built for each block.
+struct TreeDesc<'a> {
+    static_tree: Option<&'a [CtData]>, // the corresponding static tree, if any
+    extra_bits: &'a [u32],             // extra bits for each code
+    extra_base: usize,                 // base index for extra_bits
+    elems: usize,                      // max number of elements in the tree
+    max_length: usize,                 // max bit length for the codes
+}
+
+/// Scratch space for building the Huffman trees, and the estimated size of
+/// the current block with the dynamic and the static trees.
+struct TreeBuilder {
+    heap: [usize; HEAP_SIZE], // heap used to build the Huffman trees
+    heap_len: usize,          // number of elements in the heap
+    heap_max: usize,          // element of largest frequency
+    depth: [u8; HEAP_SIZE],   // depth of each subtree, used as tie breaker for trees of equal frequency
+    bl_count: [u16; MAX_BITS + 1], // number of codes at each bit length for an optimal tree
+    opt_len: isize,           // bit length of current block with optimal trees
+    static_len: isize,        // bit length of current block with static trees
+}
+
+impl TreeBuilder {
+    fn new() -> Self {
+        TreeBuilder {
+            heap: [0; HEAP_SIZE],
+            heap_len: 0,
+            heap_max: 0,
+            depth: [0; HEAP_SIZE],
+            bl_count: [0; MAX_BITS + 1],
+            opt_len: 0,
+            static_len: 0,
+        }
+    }
+
+    /// Compares two subtrees, using the tree depth as tie breaker when the
+    /// subtrees have equal frequency. This minimizes the worst case length.
+    fn smaller(&self, tree: &[CtData], n: usize, m: usize) -> bool {
+        tree[n].freq < tree[m].freq || (tree[n].freq == tree[m].freq && self.depth[n] <= self.depth[m])
+    }
+
+    /// Restores the heap property by moving down the tree starting at node
+    /// `k`, exchanging a node with the smallest of its two sons if necessary.
+    fn pqdownheap(&mut self, tree: &[CtData], mut k: usize) {
+        let v = self.heap[k];
+        let mut j = k << 1; // left son of k
+        while j <= self.heap_len {
+            // Set j to the smallest of the two sons
+            if j < self.heap_len && self.smaller(tree, self.heap[j + 1], self.heap[j]) {
+                j += 1;
+            }
+            // Exit if v is smaller than both sons
+            if self.smaller(tree, v, self.heap[j]) {
+                break;
+            }
+            // Exchange v with the smallest son
+            self.heap[k] = self.heap[j];
+            k = j;
+            j <<= 1;
+        }
+        self.heap[k] = v;
+    }
+
+    /// Constructs one Huffman tree and assigns the code bit strings and
+    /// lengths. Updates `opt_len` and `static_len`, and returns the largest
+    /// code with non zero frequency.
+    fn build_tree(&mut self, tree: &mut [CtData], desc: &TreeDesc) -> usize {
+        let mut max_code: isize = -1;
+
+        // Construct the initial heap, with least frequent element in
+        // heap[1]. heap[0] is not used.
+        self.heap_len = 0;
+        self.heap_max = HEAP_SIZE;
+        for (n, node) in tree[..desc.elems].iter_mut().enumerate() {
+            if node.freq != 0 {
+                self.heap_len += 1;
+                self.heap[self.heap_len] = n;
+                max_code = n as isize;
+                self.depth[n] = 0;
+            } else {
+                node.len = 0;
+            }
+        }
+
+        // The pkzip format requires that at least one distance code exists,
+        // and that at least one bit should be sent even if there is only one
+        // possible code. So to avoid special checks later on we force at
+        // least two codes of non zero frequency.
+        while self.heap_len < 2 {
+            let new = if max_code < 2 {
+                max_code += 1;
+                max_code as usize
+            } else {
+                0
+            };
+            self.heap_len += 1;
+            self.heap[self.heap_len] = new;
+            tree[new].freq = 1;
+            self.depth[new] = 0;
+            self.opt_len -= 1;
+            if let Some(stree) = desc.static_tree {
+                self.static_len -= stree[new].len as isize;
+            }
+        }
+        let max_code = max_code as usize;
+
+        // The elements heap[heap_len/2+1 .. heap_len] are leaves of the tree,
+        // establish sub-heaps of increasing lengths
+        for n in (1..=self.heap_len / 2).rev() {
+            self.pqdownheap(tree, n);
+        }
+
+        // Construct the Huffman tree by repeatedly combining the least two
+        // frequent nodes.
+        let mut node = desc.elems; // next internal node of the tree
+        loop {
+            // n = node of least frequency
+            let n = self.heap[1];
+            self.heap[1] = self.heap[self.heap_len];
+            self.heap_len -= 1;
+            self.pqdownheap(tree, 1);
+            // m = node of next least frequency
+            let m = self.heap[1];
+
+            // keep the nodes sorted by frequency
+            self.heap_max -= 1;
+            self.heap[self.heap_max] = n;
+            self.heap_max -= 1;
+            self.heap[self.heap_max] = m;
+
+            // Create a new node father of n and m
+            tree[node].freq = tree[n].freq + tree[m].freq;
+            self.depth[node] = self.depth[n].max(self.depth[m]) + 1;
+            tree[n].dad = node as u16;
+            tree[m].dad = node as u16;
+
+            // and insert the new node in the heap
+            self.heap[1] = node;
+            node += 1;
+            self.pqdownheap(tree, 1);
+
+            if self.heap_len < 2 {
+                break;
+            }
+        }
+        self.heap_max -= 1;
+        self.heap[self.heap_max] = self.heap[1];
+
+        // At this point, the fields freq and dad are set. We can now
+        // generate the bit lengths.
+        self.gen_bitlen(tree, desc, max_code);
+
+        // The field len is now set, we can generate the bit codes
+        gen_codes(&mut tree[..=max_code], &self.bl_count);
+        max_code
+    }
+
+    /// Computes the optimal bit lengths for a tree and updates the total bit
+    /// length for the current block. The lengths are limited to
+    /// `desc.max_length`, in which case the tree is no longer optimal.
+    fn gen_bitlen(&mut self, tree: &mut [CtData], desc: &TreeDesc, max_code: usize) {
+        self.bl_count = [0; MAX_BITS + 1];
+
+        // In a first pass, compute the optimal bit lengths (which may
+        // overflow in the case of the bit length tree).
+        tree[self.heap[self.heap_max]].len = 0; // root of the heap
+
+        let mut overflow = 0isize; // number of elements with bit length too large
+        for h in self.heap_max + 1..HEAP_SIZE {
+            let n = self.heap[h];
+            let mut bits = tree[tree[n].dad as usize].len as usize + 1;
+            if bits > desc.max_length {
+                bits = desc.max_length;
+                overflow += 1;
+            }
+            tree[n].len = bits as u16;
+            // We overwrite the len of internal nodes, which is no longer needed
+
+            if n > max_code {
+                continue; // not a leaf node
+            }
+
+            self.bl_count[bits] += 1;
+            let xbits = if n >= desc.extra_base { desc.extra_bits[n - desc.extra_base] as usize } else { 0 };
+            let f = tree[n].freq as isize;
+            self.opt_len += f * (bits + xbits) as isize;
+            if let Some(stree) = desc.static_tree {
+                self.static_len += f * (stree[n].len as usize + xbits) as isize;
+            }
+        }
+        if overflow == 0 {
+            return;
+        }
+
+        // Find the first bit length which could increase
+        while overflow > 0 {
+            let mut bits = desc.max_length - 1;
+            while self.bl_count[bits] == 0 {
+                bits -= 1;
+            }
+            self.bl_count[bits] -= 1; // move one leaf down the tree
+            self.bl_count[bits + 1] += 2; // move one overflow item as its brother
+            self.bl_count[desc.max_length] -= 1;
+            // The brother of the overflow item also moves one step up,
+            // but this does not affect bl_count[max_length]
+            overflow -= 2;
+        }
+
+        // Now recompute all bit lengths, scanning in increasing frequency.
+        let mut h = HEAP_SIZE;
+        for bits in (1..=desc.max_length).rev() {
+            let mut n = self.bl_count[bits];
+            while n != 0 {
+                h -= 1;
+                let m = self.heap[h];
+                if m > max_code {
+                    continue;
+                }
+                if tree[m].len as usize != bits {
+                    self.opt_len += (bits as isize - tree[m].len as isize) * tree[m].freq as isize;
+                    tree[m].len = bits as u16;
+                }
+                n -= 1;
+            }
+        }
+    }
+}
+
+/// Scans a literal or distance tree to determine the frequencies of the
+/// codes in the bit length tree.
+fn scan_tree(tree: &[CtData], max_code: usize, bl_tree: &mut [CtData]) {
+    // guard: the length after the last code never repeats
+    let len_at = |n: usize| if n <= max_code { tree[n].len as usize } else { 0xffff };
+
+    let mut prevlen = usize::MAX; // last emitted length
+    let mut nextlen = len_at(0); // length of next code
+    let mut count = 0; // repeat count of the current code
+    let (mut max_count, mut min_count) = if nextlen == 0 { (138, 3) } else { (7, 4) };
+
+    for n in 0..=max_code {
+        let curlen = nextlen;
+        nextlen = len_at(n + 1);
+        count += 1;
+        if count < max_count && curlen == nextlen {
+            continue;
+        } else if count < min_count {
+            bl_tree[curlen].freq += count as u32;
+        } else if curlen != 0 {
+            if curlen != prevlen {
+                bl_tree[curlen].freq += 1;
+            }
+            bl_tree[REP_3_6].freq += 1;
+        } else if count <= 10 {
+            bl_tree[REPZ_3_10].freq += 1;
+        } else {
+            bl_tree[REPZ_11_138].freq += 1;
+        }
+        count = 0;
+        prevlen = curlen;
+        (max_count, min_count) = if nextlen == 0 {
+            (138, 3)
+        } else if curlen == nextlen {
+            (6, 3)
+        } else {
+            (7, 4)
+        };
+    }
+}
+
+/// Sends a literal or distance tree in compressed form, using the codes in
+/// `bl_tree`.
+fn send_tree(bits: &mut BitBuf, tree: &[CtData], max_code: usize, bl_tree: &[CtData]) {
+    let len_at = |n: usize| if n <= max_code { tree[n].len as usize } else { 0xffff };
+
+    let mut prevlen = usize::MAX;
+    let mut nextlen = len_at(0);
+    let mut count = 0;
+    let (mut max_count, mut min_count) = if nextlen == 0 { (138, 3) } else { (7, 4) };
+
+    for n in 0..=max_code {
+        let curlen = nextlen;
+        nextlen = len_at(n + 1);
+        count += 1;
+        if count < max_count && curlen == nextlen {
+            continue;
+        } else if count < min_count {
+            for _ in 0..count {
+                bits.send_code(curlen, bl_tree);
+            }
+        } else if curlen != 0 {
+            if curlen != prevlen {
+                bits.send_code(curlen, bl_tree);
+                count -= 1;
+            }
+            bits.send_code(REP_3_6, bl_tree);
+            bits.send_bits(count - 3, 2);
+        } else if count <= 10 {
+            bits.send_code(REPZ_3_10, bl_tree);
+            bits.send_bits(count - 3, 3);
+        } else {
+            bits.send_code(REPZ_11_138, bl_tree);
+            bits.send_bits(count - 11, 7);
+        }
+        count = 0;
+        prevlen = curlen;
+        (max_count, min_count) = if nextlen == 0 {
+            (138, 3)
+        } else if curlen == nextlen {
+            (6, 3)
+        } else {
+            (7, 4)
+        };
+    }
+}
+
+/// Bit-level output: the codes are packed LSB first into bytes.
+struct BitBuf {
+    bi_buf: u32,   // bits waiting to be written, starting at the LSB
+    bi_valid: u32, // number of valid bits in bi_buf
+    out: Vec<u8>,  // encoded blocks not yet handed to the writer
+}
+
+impl BitBuf {
+    fn send_code(&mut self, c: usize, tree: &[CtData]) {
+        self.send_bits(tree[c].code as u32, tree[c].len as u32);
+    }
+
+    /// Sends a value on a given number of bits, LSB first.
+    fn send_bits(&mut self, value: u32, length: u32) {
+        self.bi_buf |= value << self.bi_valid;
+        self.bi_valid += length;
+        while self.bi_valid >= 8 {
+            self.out.push(self.bi_buf as u8);
+            self.bi_buf >>= 8;
+            self.bi_valid -= 8;
+        }
+    }
+
+    /// Writes out any remaining bits in an incomplete byte.
+    fn bi_windup(&mut self) {
+        if self.bi_valid > 0 {
+            self.out.push(self.bi_buf as u8);
+        }
+        self.bi_buf = 0;
+        self.bi_valid = 0;
+    }
+
+    /// Copies a stored block, preceded by its length and one's complement.
+    fn copy_block(&mut self, buf: &[u8]) {
+        self.bi_windup(); // align on byte boundary
+        let len = buf.len() as u16;
+        self.out.extend_from_slice(&len.to_le_bytes());
+        self.out.extend_from_slice(&(!len).to_le_bytes());
+        self.out.extend_from_slice(buf);
+    }
+}
+
+/// Symbols of the block being built, their frequencies, and the bit buffer
+/// they are encoded into.
+pub struct Trees {
+    dyn_ltree: [CtData; HEAP_SIZE],       // literal and length tree
+    dyn_dtree: [CtData; 2 * D_CODES + 1], // distance tree
+    bl_tree: [CtData; 2 * BL_CODES + 1],  // Huffman tree for the bit lengths
+    builder: TreeBuilder,
+    l_buf: Vec<u8>,   // literal byte, or match length - MIN_MATCH
+    d_buf: Vec<u16>,  // match distance, 0 for a literal
+    last_dist: usize, // number of matches in the current block
+    block_len: usize, // number of input bytes covered by the current block
+    level: usize,     // compression level, enables the early flush heuristic
+    bits: BitBuf,
+    compressed_len: usize, // total bytes handed to the writer
+    #[cfg(test)]
+    tallied: Vec<(usize, usize)>, // every symbol passed to ct_tally
+}
+
+impl Trees {
+    pub fn new(level: usize) -> Self {
+        let mut trees = Trees {
+            dyn_ltree: [CtData::default(); HEAP_SIZE],
+            dyn_dtree: [CtData::default(); 2 * D_CODES + 1],
+            bl_tree: [CtData::default(); 2 * BL_CODES + 1],
+            builder: TreeBuilder::new(),
+            l_buf: Vec::with_capacity(LIT_BUFSIZE),
+            d_buf: Vec::with_capacity(LIT_BUFSIZE),
+            last_dist: 0,
+            block_len: 0,
+            level,
+            bits: BitBuf { bi_buf: 0, bi_valid: 0, out: Vec::new() },
+            compressed_len: 0,
+            #[cfg(test)]
+            tallied: Vec::new(),
+        };
+        trees.init_block();
+        trees
+    }
+
+    /// Resets the frequencies and the symbol buffers for a new block.
+    fn init_block(&mut self) {
+        self.dyn_ltree[..L_CODES].iter_mut().for_each(|node| node.freq = 0);
+        self.dyn_dtree[..D_CODES].iter_mut().for_each(|node| node.freq = 0);
+        self.bl_tree[..BL_CODES].iter_mut().for_each(|node| node.freq = 0);
+        self.dyn_ltree[END_BLOCK].freq = 1;
+        self.builder.opt_len = 0;
+        self.builder.static_len = 0;
+        self.l_buf.clear();
+        self.d_buf.clear();
+        self.last_dist = 0;
+        self.block_len = 0;
+    }
+
+    /// Saves a match or literal byte for the current block. `dist` is the
+    /// distance of the match, or 0 for a literal; `lc` is the match length
+    /// minus MIN_MATCH, or the literal byte. Returns true if the current
+    /// block must be flushed: when the buffers are full, or when the block
+    /// looks compressible enough that starting a new one should pay off.
+    pub fn ct_tally(&mut self, dist: usize, lc: usize) -> bool {
+        #[cfg(test)]
+        self.tallied.push((dist, lc));
+        self.l_buf.push(lc as u8);
+        self.d_buf.push(dist as u16);
+        if dist == 0 {
+            // lc is the unmatched char
+            self.dyn_ltree[lc].freq += 1;
+            self.block_len += 1;
+        } else {
+            // Here, lc is the match length - MIN_MATCH
+            self.dyn_ltree[TABLES.length_code[lc] as usize + LITERALS + 1].freq += 1;
+            self.dyn_dtree[d_code(dist - 1)].freq += 1;
+            self.last_dist += 1;
+            self.block_len += lc + MIN_MATCH;
+        }
+
+        // Try to guess if it is profitable to stop the current block here
+        let last_lit = self.l_buf.len();
+        if self.level > 2 && last_lit & 0xfff == 0 {
+            // Compute an upper bound for the compressed length
+            let mut out_length = last_lit * 8;
+            for (node, &extra) in self.dyn_dtree[..D_CODES].iter().zip(EXTRA_DBITS.iter()) {
+                out_length += node.freq as usize * (5 + extra as usize);
+            }
+            out_length >>= 3;
+            if self.last_dist < last_lit / 2 && out_length < self.block_len / 2 {
+                return true;
+            }
+        }
+        // We avoid equality with LIT_BUFSIZE because of wraparound at 64K
+        // on 16 bit machines and because stored blocks are restricted to
+        // 64K-1 bytes.
+        last_lit == LIT_BUFSIZE - 1 || self.last_dist == DIST_BUFSIZE
+    }
+
+    /// Whether any symbol has been tallied since the last flush.
+    pub fn has_symbols(&self) -> bool {
+        !self.l_buf.is_empty()
+    }
+
+    /// Determines the best encoding for the current block (stored, static
+    /// trees or dynamic trees) and appends it to the pending output. `buf`
+    /// holds the input bytes of the block, or None if they are no longer in
+    /// the window, in which case a stored block cannot be used. The last
+    /// block is padded to a byte boundary.
+    pub fn flush_block(&mut self, buf: Option<&[u8]>, eof: bool) {
+        // Construct the literal and distance trees
+        let l_desc = TreeDesc {
+            static_tree: Some(&TABLES.static_ltree),
+            extra_bits: &EXTRA_LBITS,
+            extra_base: LITERALS + 1,
+            elems: L_CODES,
+            max_length: MAX_BITS,
+        };
+        let l_max_code = self.builder.build_tree(&mut self.dyn_ltree, &l_desc);
+        let d_desc = TreeDesc {
+            static_tree: Some(&TABLES.static_dtree),
+            extra_bits: &EXTRA_DBITS,
+            extra_base: 0,
+            elems: D_CODES,
+            max_length: MAX_BITS,
+        };
+        let d_max_code = self.builder.build_tree(&mut self.dyn_dtree, &d_desc);
+        // At this point, opt_len and static_len are the total bit lengths of
+        // the compressed block data, excluding the tree representations.
+
+        // Build the bit length tree for the above two trees, and get the
+        // index in BL_ORDER of the last bit length code to send.
+        let max_blindex = self.build_bl_tree(l_max_code, d_max_code);
+
+        // Determine the best encoding. Compute first the block length in
+        // bytes, including the block header
+        let mut opt_lenb = (self.builder.opt_len + 3 + 7) as usize >> 3;
+        let static_lenb = (self.builder.static_len + 3 + 7) as usize >> 3;
+        if static_lenb <= opt_lenb {
+            opt_lenb = static_lenb;
+        }
+
+        match buf {
+            // 4: two words for the lengths
+            Some(buf) if buf.len() + 4 <= opt_lenb => {
+                self.bits.send_bits((STORED_BLOCK << 1) + eof as u32, 3);
+                self.bits.copy_block(buf);
+            }
+            _ if static_lenb == opt_lenb => {
+                self.bits.send_bits((STATIC_TREES << 1) + eof as u32, 3);
+                compress_block(&mut self.bits, &self.l_buf, &self.d_buf, &TABLES.static_ltree, &TABLES.static_dtree);
+            }
+            _ => {
+                self.bits.send_bits((DYN_TREES << 1) + eof as u32, 3);
+                self.send_all_trees(l_max_code + 1, d_max_code + 1, max_blindex + 1);
+                compress_block(&mut self.bits, &self.l_buf, &self.d_buf, &self.dyn_ltree, &self.dyn_dtree);
+            }
+        }
+        self.init_block();
+
+        if eof {
+            self.bits.bi_windup();
+        }
+    }
+
+    /// Sends an empty stored block, which byte-aligns the output: the
+    /// marker of a sync flush (00 00 ff ff once aligned).
+    pub fn align_stored(&mut self) {
+        self.bits.send_bits(STORED_BLOCK << 1, 3);
+        self.bits.copy_block(&[]);
+    }
+
+    /// Constructs the Huffman tree for the bit lengths and returns the index
+    /// in BL_ORDER of the last bit length code to send.
+    fn build_bl_tree(&mut self, l_max_code: usize, d_max_code: usize) -> usize {
+        // Determine the bit length frequencies for literal and distance trees
+        scan_tree(&self.dyn_ltree, l_max_code, &mut self.bl_tree);
+        scan_tree(&self.dyn_dtree, d_max_code, &mut self.bl_tree);
+
+        // Build the bit length tree; opt_len now includes the length of the
+        // tree representations, except the lengths of the bit lengths codes
+        // and the 5+5+4 bits for the counts.
+        let bl_desc = TreeDesc {
+            static_tree: None,
+            extra_bits: &EXTRA_BLBITS,
+            extra_base: 0,
+            elems: BL_CODES,
+            max_length: MAX_BL_BITS,
+        };
+        self.builder.build_tree(&mut self.bl_tree, &bl_desc);
+
+        // Determine the number of bit length codes to send. The pkzip format
+        // requires that at least 4 bit length codes be sent.
+        let mut max_blindex = BL_CODES - 1;
+        while max_blindex >= 3 && self.bl_tree[BL_ORDER[max_blindex]].len == 0 {
+            max_blindex -= 1;
+        }
+        // Update opt_len to include the bit length tree and counts
+        self.builder.opt_len += (3 * (max_blindex + 1) + 5 + 5 + 4) as isize;
+        max_blindex
+    }
+
+    /// Sends the header for a block using dynamic Huffman trees: the counts,
+    /// the lengths of the bit length codes, the literal tree and the distance
+    /// tree.
+    fn send_all_trees(&mut self, lcodes: usize, dcodes: usize, blcodes: usize) {
+        self.bits.send_bits((lcodes - 257) as u32, 5);
+        self.bits.send_bits((dcodes - 1) as u32, 5);
+        self.bits.send_bits((blcodes - 4) as u32, 4);
+        for &code in &BL_ORDER[..blcodes] {
+            self.bits.send_bits(self.bl_tree[code].len as u32, 3);
+        }
+        send_tree(&mut self.bits, &self.dyn_ltree, lcodes - 1, &self.bl_tree);
+        send_tree(&mut self.bits, &self.dyn_dtree, dcodes - 1, &self.bl_tree);
+    }
+
+    /// Hands the complete bytes encoded so far to `output`, returning how
+    /// many were written.
+    pub fn write_pending(&mut self, output: &mut dyn Write) -> io::Result<usize> {
+        output.write_all(&self.bits.out)?;
+        let written = self.bits.out.len();
+        self.bits.out.clear();
+        self.compressed_len += written;
+        Ok(written)
+    }
+
+    /// Total number of bytes handed to the writer so far.
+    pub fn compressed_len(&self) -> usize {
+        self.compressed_len
+    }
+
+    /// Every symbol tallied so far, as (dist, lc) pairs.
+    #[cfg(test)]
+    pub(crate) fn tallied_symbols(&self) -> Vec<(usize, usize)> {
+        self.tallied.clone()
+    }
+}
+
+/// Sends the block data compressed using the given Huffman trees.
+fn compress_block(bits: &mut BitBuf, l_buf: &[u8], d_buf: &[u16], ltree: &[CtData], dtree: &[CtData]) {
+    for (&lc, &dist) in l_buf.iter().zip(d_buf.iter()) {
+        let lc = lc as usize;
+        let dist = dist as usize;
+        if dist == 0 {
+            bits.send_code(lc, ltree); // send a literal byte
+        } else {
+            // Here, lc is the match length - MIN_MATCH
+            let code = TABLES.length_code[lc] as usize;
+            bits.send_code(code + LITERALS + 1, ltree); // send the length code
+            let extra = EXTRA_LBITS[code];
+            if extra != 0 {
+                bits.send_bits((lc - TABLES.base_length[code] as usize) as u32, extra);
+            }
+
+            let dist = dist - 1; // dist is now the match distance - 1
+            let code = d_code(dist);
+            bits.send_code(code, dtree); // send the distance code
+            let extra = EXTRA_DBITS[code];
+            if extra != 0 {
+                bits.send_bits((dist - TABLES.base_dist[code] as usize) as u32, extra);
+            }
+        }
+    }
+    bits.send_code(END_BLOCK, ltree);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tally_reports_full_buffers() {
+        // Level 1 disables the early flush heuristic
+        let mut trees = Trees::new(1);
+        for i in 0..LIT_BUFSIZE - 2 {
+            assert!(!trees.ct_tally(0, i & 0xff));
+        }
+        assert!(trees.ct_tally(0, 0));
+
+        trees.flush_block(None, false);
+        assert!(!trees.has_symbols());
+        assert!(!trees.ct_tally(1, 0));
+    }
+
+    #[test]
+    fn test_flat_block_is_stored() {
+        // Every byte value once: no Huffman code can beat 8 bits per byte
+        let data: Vec<u8> = (0..=255).collect();
+        let mut trees = Trees::new(6);
+        for &b in &data {
+            trees.ct_tally(0, b as usize);
+        }
+        trees.flush_block(Some(&data), true);
+        let mut out = Vec::new();
+        trees.write_pending(&mut out).unwrap();
+        // Header bits BFINAL=1, BTYPE=00, then LEN and NLEN on a byte boundary
+        assert_eq!(out[..5], [0x01, 0x00, 0x01, 0xff, 0xfe]);
+        assert_eq!(out[5..], data[..]);
+        assert_eq!(trees.compressed_len(), out.len());
+    }
+
+    #[test]
+    fn test_skewed_block_uses_dynamic_trees() {
+        let mut trees = Trees::new(6);
+        for i in 0..1000 {
+            trees.ct_tally(0, if i % 10 == 0 { b'b' } else { b'a' } as usize);
+        }
+        trees.flush_block(None, true);
+        let mut out = Vec::new();
+        trees.write_pending(&mut out).unwrap();
+        assert_eq!(out[0] & 0x07, (DYN_TREES << 1 | 1) as u8);
+        // About one bit per symbol instead of eight with the static trees
+        assert!(out.len() < 200, "{} bytes", out.len());
+    }
+}
@@ -21,7 +21,7 @@ pub fn clear_bufs(buf: &mut [u8]) {
 // Error handling function for gzip-specific errors
 pub fn gzip_error(message: &str) -> io::Result<()> {
     eprintln!("gzip error: {}", message);
-    Err(io::Error::new(io::ErrorKind::Other, message))
+    Err(io::Error::other(message))
 }
 
 // Convert a string to lowercase
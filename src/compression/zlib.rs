@@ -0,0 +1,283 @@
+// src/compression/zlib.rs
+//
+// zlib framing (RFC 1950) around the same deflate data as gzip members:
+// a two byte CMF/FLG header, an optional preset dictionary id, and the
+// Adler-32 of the uncompressed data as a big-endian trailer.
+
+use std::io::{self, Write};
+
+use crate::compression::deflate::{
+    deflate_window, flush_block, push_input, set_dictionary, set_eof, DeflateOptions, WSIZE,
+};
+use crate::compression::lm_init::{initialize_longest_match, DeflateState};
+use crate::compression::trees::Trees;
+use crate::compression::CompressionLevel;
+use crate::util::adler32::adler32;
+
+pub const ZLIB_METHOD_DEFLATE: u8 = 8;
+pub const ZLIB_FLAG_FDICT: u8 = 0x20;
+
+/// Information carried in a zlib header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ZlibHeader {
+    /// Base two logarithm of the window size, 8 to 15 (CINFO + 8).
+    pub window_bits: u8,
+    /// FLEVEL: 0 fastest, 1 fast, 2 default, 3 best compression. It is
+    /// informational only.
+    pub level: u8,
+    /// Adler-32 of the preset dictionary the data was compressed with
+    /// (FDICT), if any.
+    pub dict_id: Option<u32>,
+}
+
+impl ZlibHeader {
+    /// The header describing data compressed at `level` with our 32K
+    /// window.
+    pub fn new(level: CompressionLevel) -> Self {
+        // 与 zlib 相同的 FLEVEL 取值
+        let flevel = match level.level() {
+            1 => 0,
+            2..=5 => 1,
+            6 => 2,
+            _ => 3,
+        };
+        ZlibHeader { window_bits: 15, level: flevel, dict_id: None }
+    }
+}
+
+/// Writes a zlib header: CMF, FLG with its check bits, and DICTID when a
+/// preset dictionary is used.
+pub fn write_zlib_header<W: Write + ?Sized>(output: &mut W, header: &ZlibHeader) -> io::Result<()> {
+    let cmf = ((header.window_bits - 8) << 4) | ZLIB_METHOD_DEFLATE;
+    let mut flg = header.level << 6;
+    if header.dict_id.is_some() {
+        flg |= ZLIB_FLAG_FDICT;
+    }
+    // FCHECK: CMF*256 + FLG must be a multiple of 31
+    let rem = (cmf as u16 * 256 + flg as u16) % 31;
+    if rem != 0 {
+        flg += 31 - rem as u8;
+    }
+    output.write_all(&[cmf, flg])?;
+
+    if let Some(dict_id) = header.dict_id {
+        output.write_all(&dict_id.to_be_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn write_zlib_footer<W: Write + ?Sized>(output: &mut W, adler: u32) -> io::Result<()> {
+    // The Adler-32 value, most significant byte first
+    output.write_all(&adler.to_be_bytes())
+}
+
+/// A zlib compressor implementing `Write`, the zlib counterpart of
+/// `GzEncoder`: call `finish` to write the last block and the trailer.
+pub struct ZlibEncoder<W: Write> {
+    inner: Option<W>,
+    header: Option<ZlibHeader>, // until written
+    state: DeflateState,
+    trees: Trees,
+    level: usize,
+    adler: u32,
+    tail: Vec<u8>,  // last block and trailer, until written
+    finished: bool, // whether the trailer has been written
+}
+
+impl<W: Write> ZlibEncoder<W> {
+    pub fn new(inner: W, options: &DeflateOptions) -> Self {
+        let mut flags = 0;
+        let mut state = DeflateState::new(2 * WSIZE);
+        // CompressionLevel only holds valid levels
+        initialize_longest_match(&mut state, options.level.level(), &mut flags)
+            .expect("compression level out of range");
+        ZlibEncoder {
+            inner: Some(inner),
+            header: Some(ZlibHeader::new(options.level)),
+            state,
+            trees: Trees::new(options.level.level()),
+            level: options.level.level(),
+            adler: 1,
+            tail: Vec::new(),
+            finished: false,
+        }
+    }
+
+    /// Creates an encoder whose data may refer to a preset dictionary (up
+    /// to 32K); its Adler-32 goes into the header (FDICT), and decoders
+    /// need the same bytes, see `ZlibDecoder::with_dictionary`.
+    pub fn with_dictionary(inner: W, options: &DeflateOptions, dictionary: &[u8]) -> Self {
+        let mut encoder = ZlibEncoder::new(inner, options);
+        set_dictionary(&mut encoder.state, dictionary);
+        if let Some(header) = &mut encoder.header {
+            header.dict_id = Some(adler32(1, dictionary));
+        }
+        encoder
+    }
+
+    /// The underlying writer.
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().expect("encoder already finished")
+    }
+
+    /// The underlying writer, mutably. Writing to it directly corrupts the
+    /// zlib stream.
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.as_mut().expect("encoder already finished")
+    }
+
+    /// Completes the zlib stream and returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.try_finish()?;
+        Ok(self.inner.take().expect("encoder already finished"))
+    }
+
+    /// Writes the last block and the trailer, keeping the encoder; does
+    /// nothing once it has succeeded, and may be called again after an
+    /// error. Nothing may be written afterwards.
+    pub fn try_finish(&mut self) -> io::Result<()> {
+        if self.finished {
+            return Ok(());
+        }
+        self.write_header()?;
+        if !self.state.eofile {
+            // 最后一块和 Adler-32 先编码到内存中，写入失败后可以重试
+            set_eof(&mut self.state);
+            deflate_window(&mut self.state, &mut self.trees, self.level, false, &mut self.tail)?;
+            flush_block(&mut self.state, &mut self.trees, &mut self.tail, true)?;
+            write_zlib_footer(&mut self.tail, self.adler)?;
+        }
+        let output = self.inner.as_mut().expect("encoder already finished");
+        output.write_all(&self.tail)?;
+        self.tail.clear();
+        output.flush()?;
+        self.finished = true;
+        Ok(())
+    }
+
+    /// Writes the header if it has not been written yet; it is kept until
+    /// the write succeeds.
+    fn write_header(&mut self) -> io::Result<()> {
+        if let Some(header) = &self.header {
+            let mut bytes = Vec::new();
+            write_zlib_header(&mut bytes, header)?;
+            self.inner.as_mut().expect("encoder already finished").write_all(&bytes)?;
+            self.header = None;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for ZlibEncoder<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.state.eofile {
+            return Err(io::Error::other("write after the zlib stream was finished"));
+        }
+        self.write_header()?;
+        let output = self.inner.as_mut().expect("encoder already finished");
+
+        let mut rest = buf;
+        while !rest.is_empty() {
+            let n = push_input(&mut self.state, rest);
+            self.adler = adler32(self.adler, &rest[..n]);
+            rest = &rest[n..];
+            deflate_window(&mut self.state, &mut self.trees, self.level, false, output)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.as_mut().expect("encoder already finished").flush()
+    }
+}
+
+impl<W: Write> Drop for ZlibEncoder<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.try_finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zlib_header_check_bits() {
+        for level in 1..=9 {
+            let mut header = ZlibHeader::new(CompressionLevel::new(level).unwrap());
+            for dict_id in [None, Some(0x0102_0304)] {
+                header.dict_id = dict_id;
+                let mut bytes = Vec::new();
+                write_zlib_header(&mut bytes, &header).unwrap();
+                assert_eq!((bytes[0] as u16 * 256 + bytes[1] as u16) % 31, 0);
+                assert_eq!(bytes.len(), if dict_id.is_some() { 6 } else { 2 });
+            }
+        }
+        // The headers zlib writes at levels 1, 6 and 9
+        let mut bytes = Vec::new();
+        for level in [CompressionLevel::FAST, CompressionLevel::DEFAULT, CompressionLevel::BEST] {
+            write_zlib_header(&mut bytes, &ZlibHeader::new(level)).unwrap();
+        }
+        assert_eq!(bytes, [0x78, 0x01, 0x78, 0x9c, 0x78, 0xda]);
+    }
+
+    #[test]
+    fn test_zlib_encoder_output() {
+        let mut encoder = ZlibEncoder::new(Vec::new(), &DeflateOptions::default());
+        encoder.write_all(b"hello").unwrap();
+        // Same bytes as zlib.compress(b"hello")
+        assert_eq!(
+            encoder.finish().unwrap(),
+            [0x78, 0x9c, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00, 0x06, 0x2c, 0x02, 0x15]
+        );
+    }
+
+    /// A writer whose write number `fail_at` fails without writing
+    /// anything.
+    struct FailOnce {
+        out: Vec<u8>,
+        calls: usize,
+        fail_at: usize,
+    }
+
+    impl Write for FailOnce {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            if self.calls - 1 == self.fail_at {
+                return Err(io::Error::other("no space left"));
+            }
+            self.out.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_finish_can_be_retried_after_a_write_error() {
+        let data = b"hello, hello, hello\n".repeat(500);
+        let mut encoder = ZlibEncoder::new(Vec::new(), &DeflateOptions::default());
+        encoder.write_all(&data).unwrap();
+        let expected = encoder.finish().unwrap();
+
+        // The header write fails
+        let writer = FailOnce { out: Vec::new(), calls: 0, fail_at: 0 };
+        let mut encoder = ZlibEncoder::new(writer, &DeflateOptions::default());
+        assert!(encoder.write_all(&data).is_err());
+        encoder.write_all(&data).unwrap();
+        assert_eq!(encoder.finish().unwrap().out, expected);
+
+        // The write of the last block and trailer fails
+        let writer = FailOnce { out: Vec::new(), calls: 0, fail_at: usize::MAX };
+        let mut encoder = ZlibEncoder::new(writer, &DeflateOptions::default());
+        encoder.write_all(&data).unwrap();
+        encoder.get_mut().fail_at = encoder.get_ref().calls;
+        assert!(encoder.try_finish().is_err());
+        encoder.try_finish().unwrap();
+        assert_eq!(encoder.finish().unwrap().out, expected);
+    }
+}
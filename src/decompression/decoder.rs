@@ -0,0 +1,443 @@
+// src/decompression/decoder.rs
+//
+// Pull-style gzip decompression: a `GzDecoder` inflates the members read
+// from the wrapped reader as the caller reads from it, and checks the
+// CRC and length stored in the trailer of each. `GzDecoderWriter` is
+// the push-style counterpart: compressed data written to it is inflated
+// into the wrapped writer. Like gzip, concatenated members are decoded
+// one after another, and data after the last member that does not start
+// another one is ignored.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use crate::compression::deflate::{GzHeader, GZIP_MAGIC};
+use crate::decompression::header::read_gzip_header;
+use crate::decompression::inflate::InflateState;
+use crate::util::crc::updcrc;
+
+/// A gzip decompressor implementing `Read` and `BufRead`: reading from it
+/// yields the decompressed contents of the gzip members read from `inner`.
+/// The first header is parsed when the decoder is created; data is
+/// inflated only as it is read.
+pub struct GzDecoder<R: Read> {
+    input: BufReader<R>,
+    header: GzHeader,
+    inflate: InflateState,
+    crc: u32,  // CRC of the bytes of this member consumed so far
+    size: u64, // number of bytes of this member consumed so far
+    checked: bool, // whether the trailer has been read and checked
+    trailing_garbage: bool, // whether data that is not a member followed
+}
+
+impl<R: Read> GzDecoder<R> {
+    /// Reads the gzip header from `inner` and returns a decoder for the
+    /// data that follows.
+    pub fn new(inner: R) -> io::Result<Self> {
+        let mut input = BufReader::new(inner);
+        let header = read_gzip_header(&mut input)?;
+        Ok(GzDecoder {
+            input,
+            header,
+            inflate: InflateState::new(),
+            crc: 0,
+            size: 0,
+            checked: false,
+            trailing_garbage: false,
+        })
+    }
+
+    /// The header of the first gzip member.
+    pub fn header(&self) -> &GzHeader {
+        &self.header
+    }
+
+    /// Whether the last member was followed by data that is not another
+    /// member, which has been ignored (gzip's "trailing garbage ignored").
+    pub fn trailing_garbage(&self) -> bool {
+        self.trailing_garbage
+    }
+
+    /// The underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.input.get_ref()
+    }
+
+    /// Reads the trailer after the deflate data and compares it with the
+    /// data handed out.
+    fn check_trailer(&mut self) -> io::Result<()> {
+        let mut trailer = [0u8; 8];
+        self.input.read_exact(&mut trailer)?;
+        check_trailer(&trailer, self.crc, self.size)?;
+        self.checked = true;
+        Ok(())
+    }
+
+    /// After a trailer, starts on the next member if one follows. Returns
+    /// false at the end of the input or of the members.
+    fn next_member(&mut self) -> io::Result<bool> {
+        // The buffer may hold a single byte, as with short reads: read both
+        // bytes of the magic number before deciding.
+        let mut magic = [0u8; 2];
+        let mut len = 0;
+        while len < magic.len() {
+            let n = self.input.read(&mut magic[len..])?;
+            if n == 0 {
+                break;
+            }
+            len += n;
+        }
+        if len == 0 {
+            return Ok(false);
+        }
+        if magic[..len] != GZIP_MAGIC.to_le_bytes() {
+            self.trailing_garbage = true;
+            return Ok(false);
+        }
+        read_gzip_header(&mut (&magic[..]).chain(&mut self.input))?;
+        self.inflate = InflateState::new();
+        self.crc = 0;
+        self.size = 0;
+        self.checked = false;
+        Ok(true)
+    }
+}
+
+/// Compares the gzip trailer with the CRC and length of the data
+/// decompressed (the end of gzip's unzip).
+fn check_trailer(trailer: &[u8; 8], crc: u32, size: u64) -> io::Result<()> {
+    let stored_crc = u32::from_le_bytes([trailer[0], trailer[1], trailer[2], trailer[3]]);
+    let stored_size = u32::from_le_bytes([trailer[4], trailer[5], trailer[6], trailer[7]]);
+    if stored_crc != crc {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid compressed data--crc error"));
+    }
+    if stored_size != size as u32 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "invalid compressed data--length error"));
+    }
+    Ok(())
+}
+
+impl<R: Read> BufRead for GzDecoder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        while self.inflate.fill_buf(&mut self.input)?.is_empty() && !self.checked {
+            self.check_trailer()?;
+            if !self.next_member()? {
+                break;
+            }
+        }
+        self.inflate.fill_buf(&mut self.input)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let pending = self.inflate.pending();
+        let amt = amt.min(pending.len());
+        self.crc = updcrc(self.crc, &pending[..amt]);
+        self.size += amt as u64;
+        self.inflate.consume(amt);
+    }
+}
+
+impl<R: Read> Read for GzDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let pending = self.fill_buf()?;
+        let n = pending.len().min(buf.len());
+        buf[..n].copy_from_slice(&pending[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+/// A writer that decompresses the gzip members written to it into another
+/// writer. Decompressed data is passed on as soon as the compressed data
+/// for it has been written; `finish` checks that the last member was
+/// complete.
+pub struct GzDecoderWriter<W: Write> {
+    inner: W,
+    buf: Vec<u8>,              // compressed data written but not decoded yet
+    header: Option<GzHeader>,  // the first header, once it has been read
+    in_header: bool,           // whether a member header is expected next
+    inflate: InflateState,
+    crc: u32,
+    size: u64,
+    checked: bool,             // whether the trailer has been read and checked
+    trailing_garbage: bool,    // whether data that is not a member followed
+}
+
+impl<W: Write> GzDecoderWriter<W> {
+    pub fn new(inner: W) -> Self {
+        GzDecoderWriter {
+            inner,
+            buf: Vec::new(),
+            header: None,
+            in_header: true,
+            inflate: InflateState::new(),
+            crc: 0,
+            size: 0,
+            checked: false,
+            trailing_garbage: false,
+        }
+    }
+
+    /// The header of the first gzip member, once enough data has been
+    /// written.
+    pub fn header(&self) -> Option<&GzHeader> {
+        self.header.as_ref()
+    }
+
+    /// Whether the last member was followed by data that is not another
+    /// member, which has been ignored.
+    pub fn trailing_garbage(&self) -> bool {
+        self.trailing_garbage
+    }
+
+    /// The underlying writer.
+    pub fn get_ref(&self) -> &W {
+        &self.inner
+    }
+
+    /// Checks that the last gzip member has been written completely and
+    /// returns the underlying writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.decode()?;
+        if !self.checked {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of file"));
+        }
+        self.inner.flush()?;
+        Ok(self.inner)
+    }
+
+    /// Decodes as much of the buffered input as possible. Running out of
+    /// input is not an error here: decoding resumes on the next write.
+    fn decode(&mut self) -> io::Result<()> {
+        loop {
+            if self.checked {
+                // 下一个成员，或者是尾部垃圾
+                let magic = GZIP_MAGIC.to_le_bytes();
+                if self.buf.len() < magic.len() && magic.starts_with(&self.buf) {
+                    return Ok(());
+                }
+                if !self.buf.starts_with(&magic) {
+                    self.trailing_garbage = true;
+                    self.buf.clear();
+                    return Ok(());
+                }
+                self.in_header = true;
+                self.inflate = InflateState::new();
+                self.crc = 0;
+                self.size = 0;
+                self.checked = false;
+            }
+            if self.in_header {
+                let mut rest = &self.buf[..];
+                match read_gzip_header(&mut rest) {
+                    Ok(header) => {
+                        let used = self.buf.len() - rest.len();
+                        self.buf.drain(..used);
+                        self.header.get_or_insert(header);
+                        self.in_header = false;
+                    }
+                    Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+                    Err(e) => return Err(e),
+                }
+            }
+            self.decode_member()?;
+            if !self.checked || self.buf.is_empty() {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Inflates the buffered data of the current member, and checks its
+    /// trailer once it is complete.
+    fn decode_member(&mut self) -> io::Result<()> {
+        let mut rest = &self.buf[..];
+        let result = loop {
+            let pending = match self.inflate.fill_buf(&mut rest) {
+                Ok(pending) => pending,
+                Err(e) => break Err(e),
+            };
+            if pending.is_empty() {
+                break Ok(());
+            }
+            if let Err(e) = self.inner.write_all(pending) {
+                break Err(e);
+            }
+            let n = pending.len();
+            self.crc = updcrc(self.crc, pending);
+            self.size += n as u64;
+            self.inflate.consume(n);
+        };
+        // 被中断的一步所读的字节留在缓冲区中，下次重新解码
+        let used = self.buf.len() - rest.len() - self.inflate.take_unread();
+        self.buf.drain(..used);
+        match result {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(()),
+            result => result?,
+        }
+
+        if !self.checked && self.buf.len() >= 8 {
+            let trailer: [u8; 8] = self.buf[..8].try_into().unwrap();
+            check_trailer(&trailer, self.crc, self.size)?;
+            self.buf.drain(..8);
+            self.checked = true;
+        }
+        Ok(())
+    }
+}
+
+impl<W: Write> Write for GzDecoderWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.trailing_garbage {
+            // 尾部垃圾之后的数据都被忽略，与 GzDecoder 一致
+            return Ok(buf.len());
+        }
+        self.buf.extend_from_slice(buf);
+        self.decode()?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::deflate::FLAG_FEXTRA;
+    use crate::compression::{deflate, DeflateOptions};
+    use std::io::Cursor;
+
+    fn gzip(data: &[u8], header: &GzHeader) -> Vec<u8> {
+        let mut output = Vec::new();
+        deflate(&mut Cursor::new(data), &mut output, header, &DeflateOptions::default()).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_decoder_reads_lines_lazily() {
+        let text: String = (0..50_000).map(|i| format!("request {} status {}\n", i, 200 + i % 7)).collect();
+        let header = GzHeader { filename: Some(b"access.log".to_vec()), mtime: 42, ..GzHeader::default() };
+        let compressed = gzip(text.as_bytes(), &header);
+
+        let mut decoder = GzDecoder::new(Cursor::new(&compressed)).unwrap();
+        assert_eq!(decoder.header(), &header);
+        let mut lines = (&mut decoder).lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "request 0 status 200");
+        // Only part of the input has been needed for the first line
+        assert!((decoder.get_ref().position() as usize) < compressed.len());
+
+        let rest: Vec<String> = decoder.lines().map(|line| line.unwrap()).collect();
+        assert_eq!(rest.len(), 49_999);
+        assert_eq!(rest[49_998], "request 49999 status 205");
+    }
+
+    #[test]
+    fn test_decoder_read_to_end_checks_trailer() {
+        let data = b"some data to compress, some data to compress".repeat(100);
+        let mut compressed = gzip(&data, &GzHeader::default());
+        let mut output = Vec::new();
+        GzDecoder::new(Cursor::new(&compressed)).unwrap().read_to_end(&mut output).unwrap();
+        assert_eq!(output, data);
+
+        // Corrupt the stored CRC
+        let crc_at = compressed.len() - 8;
+        compressed[crc_at] ^= 1;
+        let err = GzDecoder::new(Cursor::new(&compressed)).unwrap().read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+
+        // Truncated input
+        let truncated = &compressed[..compressed.len() - 4];
+        assert!(GzDecoder::new(Cursor::new(truncated)).unwrap().read_to_end(&mut Vec::new()).is_err());
+    }
+
+    /// A reader returning at most one byte per call, like a slow pipe.
+    struct OneByteReader<'a>(&'a [u8]);
+
+    impl Read for OneByteReader<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let n = self.0.len().min(buf.len()).min(1);
+            buf[..n].copy_from_slice(&self.0[..n]);
+            self.0 = &self.0[n..];
+            Ok(n)
+        }
+    }
+
+    #[test]
+    fn test_concatenated_members() {
+        let first = b"first member\n".repeat(300);
+        let second = b"second member\n".repeat(200);
+        let header = GzHeader { filename: Some(b"first".to_vec()), ..GzHeader::default() };
+        let mut compressed = gzip(&first, &header);
+        compressed.extend(gzip(&second, &GzHeader { filename: Some(b"second".to_vec()), ..GzHeader::default() }));
+        let expected = [first, second].concat();
+
+        let mut decoder = GzDecoder::new(Cursor::new(&compressed)).unwrap();
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, expected);
+        assert_eq!(decoder.header(), &header);
+        assert!(!decoder.trailing_garbage());
+
+        for chunk in [1, 7, compressed.len()] {
+            let mut writer = GzDecoderWriter::new(Vec::new());
+            for part in compressed.chunks(chunk) {
+                writer.write_all(part).unwrap();
+            }
+            assert_eq!(writer.header(), Some(&header));
+            assert!(!writer.trailing_garbage());
+            assert_eq!(writer.finish().unwrap(), expected, "chunk {}", chunk);
+        }
+
+        // Data after the members that is not a member is ignored, but noted
+        compressed.extend_from_slice(&[0; 10]);
+        let mut decoder = GzDecoder::new(Cursor::new(&compressed)).unwrap();
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, expected);
+        assert!(decoder.trailing_garbage());
+
+        // A reader handing out one byte at a time leaves only the first
+        // byte of the next magic number buffered
+        let mut decoder = GzDecoder::new(OneByteReader(&compressed[..compressed.len() - 10])).unwrap();
+        let mut output = Vec::new();
+        decoder.read_to_end(&mut output).unwrap();
+        assert_eq!(output, expected);
+        assert!(!decoder.trailing_garbage());
+
+        // A second member cut short is an error
+        let truncated = &compressed[..compressed.len() - 14];
+        assert!(GzDecoder::new(Cursor::new(truncated)).unwrap().read_to_end(&mut Vec::new()).is_err());
+        let mut writer = GzDecoderWriter::new(Vec::new());
+        writer.write_all(truncated).unwrap();
+        assert!(writer.finish().is_err());
+    }
+
+    #[test]
+    fn test_decoder_writer_accepts_any_write_sizes() {
+        let text: String = (0..20_000).map(|i| format!("{} {}\n", i, i * i % 1013)).collect();
+        let header = GzHeader { filename: Some(b"squares".to_vec()), ..GzHeader::default() };
+        let compressed = gzip(text.as_bytes(), &header);
+
+        // A 24-byte extra field, longer than some of the writes; without a
+        // name, the header ends with it
+        let mut extra_only = gzip(text.as_bytes(), &GzHeader::default());
+        extra_only[3] |= FLAG_FEXTRA;
+        extra_only.splice(10..10, [24, 0].into_iter().chain(0..24));
+
+        for (member, header) in [(&compressed, &header), (&extra_only, &GzHeader::default())] {
+            for chunk in [1, 5, 12, 1000, member.len()] {
+                let mut writer = GzDecoderWriter::new(Vec::new());
+                for part in member.chunks(chunk) {
+                    writer.write_all(part).unwrap();
+                }
+                assert_eq!(writer.header(), Some(header));
+                assert_eq!(writer.finish().unwrap(), text.as_bytes(), "chunk {}", chunk);
+            }
+        }
+
+        // An incomplete member is only detected by finish
+        let mut writer = GzDecoderWriter::new(Vec::new());
+        writer.write_all(&compressed[..compressed.len() - 1]).unwrap();
+        assert_eq!(writer.finish().unwrap_err().kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -0,0 +1,87 @@
+// src/decompression/format.rs
+//
+// Recognition of the compressed formats from the first bytes of the input,
+// like the magic number tests of gzip's get_method, with zlib streams
+// added.
+
+use crate::compression::deflate::GZIP_MAGIC;
+use crate::compression::zlib::ZLIB_METHOD_DEFLATE;
+use crate::decompression::unlzh::LZH_MAGIC;
+use crate::decompression::unlzw::LZW_MAGIC;
+use crate::decompression::unpack::PACK_MAGIC;
+use crate::decompression::unzip::LOCAL_HEADER_MAGIC;
+
+/// The format of some input, and so the decoder to use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Gzip,
+    /// zlib stream (RFC 1950)
+    Zlib,
+    /// compress(1), .Z
+    Lzw,
+    /// pack(1), .z
+    Pack,
+    /// PKZIP archive
+    Zip,
+    /// SCO compress -H
+    Lzh,
+    /// Anything else, such as plain text
+    Plain,
+}
+
+/// Tells the format of the data starting with `magic`; 4 bytes are enough
+/// for all formats.
+pub fn detect_format(magic: &[u8]) -> Format {
+    let Some(&[b0, b1]) = magic.first_chunk::<2>() else {
+        return Format::Plain;
+    };
+    match u16::from_le_bytes([b0, b1]) {
+        GZIP_MAGIC => return Format::Gzip,
+        LZW_MAGIC => return Format::Lzw,
+        PACK_MAGIC => return Format::Pack,
+        LZH_MAGIC => return Format::Lzh,
+        _ => {}
+    }
+    if magic.starts_with(&LOCAL_HEADER_MAGIC) {
+        return Format::Zip;
+    }
+    // zlib 没有魔数：检查压缩方法、窗口大小和 FCHECK
+    if b0 & 0x0f == ZLIB_METHOD_DEFLATE && b0 >> 4 <= 7 && (b0 as u16 * 256 + b1 as u16).is_multiple_of(31) {
+        return Format::Zlib;
+    }
+    Format::Plain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::{CompressionLevel, ZlibHeader};
+    use crate::compression::zlib::write_zlib_header;
+
+    #[test]
+    fn test_detect_magic_numbers() {
+        assert_eq!(detect_format(&[0x1f, 0x8b, 8, 0]), Format::Gzip);
+        assert_eq!(detect_format(&[0x1f, 0x9d, 0x90]), Format::Lzw);
+        assert_eq!(detect_format(&[0x1f, 0x1e, 0, 0]), Format::Pack);
+        assert_eq!(detect_format(&[0x1f, 0xa0, 0, 0x0b]), Format::Lzh);
+        assert_eq!(detect_format(b"PK\x03\x04\x14\x00"), Format::Zip);
+        // The central directory alone is not an archive gzip can read
+        assert_eq!(detect_format(b"PK\x05\x06"), Format::Plain);
+    }
+
+    #[test]
+    fn test_detect_zlib_and_plain() {
+        for level in 1..=9 {
+            let mut header = Vec::new();
+            write_zlib_header(&mut header, &ZlibHeader::new(CompressionLevel::new(level).unwrap())).unwrap();
+            assert_eq!(detect_format(&header), Format::Zlib, "level {}", level);
+        }
+        // A bad check, and a window larger than 32K
+        assert_eq!(detect_format(&[0x78, 0x9d]), Format::Plain);
+        assert_eq!(detect_format(&[0x88, 0x98]), Format::Plain);
+
+        assert_eq!(detect_format(b"hello, world\n"), Format::Plain);
+        assert_eq!(detect_format(b"\x1f"), Format::Plain);
+        assert_eq!(detect_format(b""), Format::Plain);
+    }
+}
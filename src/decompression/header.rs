@@ -0,0 +1,157 @@
+use std::io::{self, Read};
+
+use crate::compression::deflate::{
+    GzHeader, COMPRESSION_METHOD_DEFLATE, FLAG_FCOMMENT, FLAG_FEXTRA, FLAG_FHCRC, FLAG_FNAME,
+    FLAG_RESERVED, GZIP_MAGIC,
+};
+use crate::util::crc::updcrc;
+
+/// Passes the header bytes through while keeping their CRC, for FHCRC.
+struct CrcReader<'a> {
+    inner: &'a mut dyn Read,
+    crc: u32,
+}
+
+impl Read for CrcReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.crc = updcrc(self.crc, &buf[..n]);
+        Ok(n)
+    }
+}
+
+/// Reads and checks a gzip member header, leaving `input` positioned at the
+/// start of the deflate data (the get_method part of gzip.c). A header cut
+/// short fails with `UnexpectedEof`.
+pub fn read_gzip_header(input: &mut dyn Read) -> io::Result<GzHeader> {
+    let input = &mut CrcReader { inner: input, crc: 0 };
+    let mut fixed = [0u8; 10];
+    input.read_exact(&mut fixed)?;
+
+    if u16::from_le_bytes([fixed[0], fixed[1]]) != GZIP_MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not in gzip format"));
+    }
+    if fixed[2] != COMPRESSION_METHOD_DEFLATE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unknown method {} -- not supported", fixed[2]),
+        ));
+    }
+
+    let flags = fixed[3];
+    if flags & FLAG_RESERVED != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("has flags 0x{:x} -- not supported", flags),
+        ));
+    }
+
+    let mtime = u32::from_le_bytes([fixed[4], fixed[5], fixed[6], fixed[7]]);
+    // fixed[8] (extra flags) is informational only
+    let os = fixed[9];
+
+    if flags & FLAG_FEXTRA != 0 {
+        let mut len = [0u8; 2];
+        input.read_exact(&mut len)?;
+        // 必须读满 len 个字节，否则头部不完整
+        let mut extra = vec![0u8; u16::from_le_bytes(len) as usize];
+        input.read_exact(&mut extra)?;
+    }
+
+    let filename = if flags & FLAG_FNAME != 0 {
+        Some(read_zero_terminated(input)?)
+    } else {
+        None
+    };
+
+    if flags & FLAG_FCOMMENT != 0 {
+        read_zero_terminated(input)?;
+    }
+
+    if flags & FLAG_FHCRC != 0 {
+        // 头部 CRC32 的低 16 位
+        let computed = input.crc as u16;
+        let mut crc16 = [0u8; 2];
+        input.read_exact(&mut crc16)?;
+        let stored = u16::from_le_bytes(crc16);
+        if stored != computed {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("header checksum 0x{:04x} != computed checksum 0x{:04x}", stored, computed),
+            ));
+        }
+    }
+
+    Ok(GzHeader { filename, mtime, os })
+}
+
+fn read_zero_terminated(input: &mut dyn Read) -> io::Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    let mut byte = [0u8; 1];
+    loop {
+        input.read_exact(&mut byte)?;
+        if byte[0] == 0 {
+            return Ok(bytes);
+        }
+        bytes.push(byte[0]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::deflate::write_gzip_header;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_header_roundtrip() {
+        let header = GzHeader {
+            filename: Some(b"access.log".to_vec()),
+            mtime: 1_700_000_000,
+            ..GzHeader::default()
+        };
+        let mut bytes = Vec::new();
+        write_gzip_header(&mut bytes, &header, 0).unwrap();
+        assert_eq!(read_gzip_header(&mut Cursor::new(&bytes)).unwrap(), header);
+
+        // -n: neither a name nor a timestamp is stored
+        let mut bytes = Vec::new();
+        write_gzip_header(&mut bytes, &GzHeader::default(), 0).unwrap();
+        assert_eq!(bytes.len(), 10);
+        assert_eq!(&bytes[4..8], &[0, 0, 0, 0]);
+        assert_eq!(read_gzip_header(&mut Cursor::new(&bytes)).unwrap(), GzHeader::default());
+    }
+
+    #[test]
+    fn test_header_skips_optional_fields() {
+        let mut bytes = vec![0x1f, 0x8b, 8, FLAG_FEXTRA | FLAG_FNAME | FLAG_FCOMMENT | FLAG_FHCRC];
+        bytes.extend_from_slice(&[1, 0, 0, 0, 0, 3]);
+        bytes.extend_from_slice(&[3, 0, b'a', b'b', b'c']);
+        bytes.extend_from_slice(b"name\0comment\0");
+        let crc16 = updcrc(0, &bytes) as u16;
+        bytes.extend_from_slice(&crc16.to_le_bytes());
+        bytes.push(0xcc);
+
+        let mut input = Cursor::new(&bytes);
+        let header = read_gzip_header(&mut input).unwrap();
+        assert_eq!(header.filename.as_deref(), Some(&b"name"[..]));
+        assert_eq!(header.mtime, 1);
+        assert_eq!(input.position(), bytes.len() as u64 - 1);
+
+        // A wrong header CRC
+        let crc_at = bytes.len() - 3;
+        bytes[crc_at] ^= 1;
+        let err = read_gzip_header(&mut Cursor::new(&bytes)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+        assert!(err.to_string().starts_with("header checksum"));
+    }
+
+    #[test]
+    fn test_header_truncated_extra_field() {
+        let mut bytes = vec![0x1f, 0x8b, 8, FLAG_FEXTRA, 0, 0, 0, 0, 0, 3];
+        bytes.extend_from_slice(&[24, 0]);
+        bytes.extend_from_slice(&[0x55; 12]);
+        let err = read_gzip_header(&mut Cursor::new(&bytes)).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
@@ -1,95 +1,167 @@
-use std::collections::HashMap;
-
-// 霍夫曼树的节点结构
-#[derive(Debug, Clone)]
-pub struct HuffmanNode {
-    pub symbol: Option<u8>,
-    pub left: Option<Box<HuffmanNode>>,
-    pub right: Option<Box<HuffmanNode>>,
-}
-
-impl HuffmanNode {
-    pub fn new(symbol: Option<u8>) -> Self {
-        HuffmanNode {
-            symbol,
-            left: None,
-            right: None,
-        }
-    }
-
-    pub fn print_tree(&self, prefix: String) {
-        if let Some(symbol) = self.symbol {
-            println!("{}Symbol: {}", prefix, symbol);
-        } else {
-            println!("{}[Internal Node]", prefix);
-        }
-
-        if let Some(ref left) = self.left {
-            left.print_tree(format!("{}0", prefix));
-        }
-
-        if let Some(ref right) = self.right {
-            right.print_tree(format!("{}1", prefix));
-        }
-    }
-}
-
-pub fn generate_encoding_table(node: &Option<HuffmanNode>, prefix: Vec<u8>, table: &mut HashMap<u8, Vec<u8>>) {
-    if let Some(n) = node.as_ref() {
-        if let Some(symbol) = n.symbol {
-            table.insert(symbol, prefix);
-        } else {
-            if let Some(ref left) = n.left {
-                let mut left_prefix = prefix.clone();
-                left_prefix.push(0);
-                generate_encoding_table(&Some(left.as_ref().clone()), left_prefix, table);
-            }
-            if let Some(ref right) = n.right {
-                let mut right_prefix = prefix.clone();
-                right_prefix.push(1);
-                generate_encoding_table(&Some(right.as_ref().clone()), right_prefix, table);
-            }
-        }
-    }
-}
-
-
-
-// 生成霍夫曼树
-pub fn build_huffman_tree(freqs: &[u16]) -> Option<HuffmanNode> {
-    // 创建节点列表，过滤掉频率为0的符号
-    let mut nodes: Vec<_> = freqs.iter().enumerate()
-        .filter(|&(_, &freq)| freq > 0)
-        .map(|(symbol, _)| HuffmanNode::new(Some(symbol as u8)))
-        .collect();
-
-    // 当节点数大于1时合并最小的两个节点
-    while nodes.len() > 1 {
-        // 按照符号排序以确保每次都取最小的两个节点
-        nodes.sort_by_key(|node| node.symbol);
-        let left = nodes.remove(0);
-        let right = nodes.remove(0);
-
-        // 创建一个父节点，将两个子节点添加为其左右子节点
-        let mut parent = HuffmanNode::new(None);
-        parent.left = Some(Box::new(left));
-        parent.right = Some(Box::new(right));
-
-        // 将父节点添加回节点列表
-        nodes.push(parent);
-    }
-
-    // 确保返回非空树，否则返回 None
-    nodes.pop()
-}
-
-
-
-
-
-// Frees the Huffman tree by allowing Rust's ownership system to drop it
-pub fn huft_free(tree: Option<HuffmanNode>) {
-    // In Rust, memory cleanup is typically automatic,
-    // but we can define this function if we want to manually release any resources.
-    drop(tree);
-}
+use std::io;
+
+/// Decoding table for a canonical Huffman code, as used by deflate. The
+/// table is indexed by the next `bits` input bits, LSB first; each entry
+/// holds the symbol whose code is a prefix of those bits and the length of
+/// that code, or a length of 0 if no code matches.
+pub struct Huft {
+    table: Vec<(u16, u8)>,
+    bits: u32,
+}
+
+impl Huft {
+    /// Number of input bits that index the table: the longest code length.
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    /// The symbol and code length for the given input bits.
+    pub fn lookup(&self, index: u32) -> (u16, u8) {
+        self.table[(index & ((1 << self.bits) - 1)) as usize]
+    }
+}
+
+/// Builds the decoding table for the code with the given bit length for
+/// each symbol (0 for unused symbols). Incomplete codes are accepted, as
+/// gzip does; their unused bit patterns are reported when met. Fails if
+/// the lengths are over-subscribed or longer than 15 bits.
+pub fn huft_build(lengths: &[u8]) -> io::Result<Huft> {
+    const MAX_BITS: usize = 15;
+    let invalid = |msg| io::Error::new(io::ErrorKind::InvalidData, msg);
+
+    // 统计每种码长的符号个数
+    let mut count = [0u16; MAX_BITS + 1];
+    for &len in lengths {
+        if len as usize > MAX_BITS {
+            return Err(invalid("bad code length"));
+        }
+        count[len as usize] += 1;
+    }
+    count[0] = 0;
+    let max_len = (1..=MAX_BITS).rev().find(|&len| count[len] != 0).unwrap_or(1);
+
+    // 检查码长是否过多（超出二叉树容量）
+    let mut left: i32 = 1;
+    for &n in &count[1..] {
+        left = (left << 1) - n as i32;
+        if left < 0 {
+            return Err(invalid("over-subscribed code lengths"));
+        }
+    }
+
+    // 每种码长的第一个编码
+    let mut next_code = [0u32; MAX_BITS + 1];
+    let mut code = 0u32;
+    for len in 1..=MAX_BITS {
+        code = (code + count[len - 1] as u32) << 1;
+        next_code[len] = code;
+    }
+
+    // 编码按 LSB 优先发送：反转后填入所有以它为前缀的表项
+    let mut table = vec![(0u16, 0u8); 1 << max_len];
+    for (symbol, &len) in lengths.iter().enumerate() {
+        if len == 0 {
+            continue;
+        }
+        let len = len as usize;
+        let reversed = (next_code[len].reverse_bits() >> (32 - len)) as usize;
+        next_code[len] += 1;
+        for entry in table.iter_mut().skip(reversed).step_by(1 << len) {
+            *entry = (symbol as u16, len as u8);
+        }
+    }
+
+    Ok(Huft { table, bits: max_len as u32 })
+}
+
+/// Decoding tables for an MSB-first Huffman code given by its number of
+/// leaves at each length, as in pack(1) (gzip's build_tree in unpack.c).
+/// At each length the internal nodes come first and the leaves take the
+/// last codes.
+pub struct PrefixTable {
+    parents: Vec<usize>,
+    prefix_len: Vec<u8>,
+    peek_bits: usize,
+}
+
+impl PrefixTable {
+    /// Number of input bits that index the prefix table.
+    pub fn peek_bits(&self) -> usize {
+        self.peek_bits
+    }
+
+    /// The length of the code starting with the next `peek_bits()` input
+    /// bits, or 0 if the code is longer.
+    pub fn prefix_len(&self, peek: usize) -> usize {
+        self.prefix_len[peek] as usize
+    }
+
+    /// Number of internal nodes at length `len`, which is also the code of
+    /// the first leaf of that length: codes below it are longer.
+    pub fn parents(&self, len: usize) -> usize {
+        self.parents[len]
+    }
+}
+
+/// Builds the tables for the code with `leaves[len]` leaves of each length
+/// `len` (`leaves[0]` is unused); codes of up to `max_peek` bits are found
+/// in a single lookup.
+pub fn build_prefix_table(leaves: &[usize], max_peek: usize) -> PrefixTable {
+    let max_len = leaves.len() - 1;
+    let mut parents = vec![0; leaves.len()];
+    let mut nodes = 0;
+    for len in (1..=max_len).rev() {
+        nodes >>= 1;
+        parents[len] = nodes;
+        nodes += leaves[len];
+    }
+
+    // Each entry gives the length of the code that the peeked bits start
+    // with. Leaves are last at each length, so the table is filled from
+    // its end.
+    let peek_bits = max_len.min(max_peek);
+    let mut prefix_len = vec![0u8; 1 << peek_bits];
+    let mut end = prefix_len.len();
+    for (len, &count) in (1..).zip(&leaves[1..=peek_bits]) {
+        let prefixes = count << (peek_bits - len);
+        prefix_len[end - prefixes..end].fill(len as u8);
+        end -= prefixes;
+    }
+
+    PrefixTable { parents, prefix_len, peek_bits }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_huft_build_canonical_codes() {
+        // RFC 1951 example: lengths (3, 3, 3, 3, 3, 2, 4, 4) give the codes
+        // 010, 011, 100, 101, 110, 00, 1110, 1111
+        let huft = huft_build(&[3, 3, 3, 3, 3, 2, 4, 4]).unwrap();
+        assert_eq!(huft.bits(), 4);
+        let reverse = |code: u32, len: u32| code.reverse_bits() >> (32 - len);
+        assert_eq!(huft.lookup(reverse(0b00, 2)), (5, 2));
+        assert_eq!(huft.lookup(reverse(0b010, 3)), (0, 3));
+        assert_eq!(huft.lookup(reverse(0b110, 3)), (4, 3));
+        assert_eq!(huft.lookup(reverse(0b1111, 4)), (7, 4));
+
+        assert!(huft_build(&[1, 1, 1]).is_err());
+        // Incomplete: the pattern 1 has no symbol
+        assert_eq!(huft_build(&[1]).unwrap().lookup(1).1, 0);
+    }
+
+    #[test]
+    fn test_prefix_table_pack_codes() {
+        // One leaf of length 1, one of length 2 and two of length 3 give
+        // the codes 1, 01, 000 and 001
+        let table = build_prefix_table(&[0, 1, 1, 2], 2);
+        assert_eq!(table.peek_bits(), 2);
+        assert_eq!((table.parents(1), table.parents(2), table.parents(3)), (1, 1, 0));
+        assert_eq!(table.prefix_len(0b11), 1);
+        assert_eq!(table.prefix_len(0b10), 1);
+        assert_eq!(table.prefix_len(0b01), 2);
+        assert_eq!(table.prefix_len(0b00), 0);
+    }
+}
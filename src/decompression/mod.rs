@@ -1,7 +1,23 @@
 // src/decompression/mod.rs
 
+pub mod decoder;
+pub mod format;
+pub mod header;
 pub mod inflate;
+pub mod unlzh;
+pub mod unlzw;
+pub mod unpack;
+pub mod unzip;
 pub mod huft;
+pub mod zlib;
 
-pub use inflate::inflate;
+pub use decoder::{GzDecoder, GzDecoderWriter};
+pub use format::{detect_format, Format};
+pub use header::read_gzip_header;
+pub use inflate::{inflate, Inflater, Status};
+pub use unlzh::unlzh;
+pub use unlzw::unlzw;
+pub use unpack::unpack;
+pub use unzip::{unzip, ZipHeader};
 
+pub use zlib::{read_zlib_header, ZlibDecoder};
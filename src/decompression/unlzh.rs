@@ -0,0 +1,315 @@
+// src/decompression/unlzh.rs
+//
+// Decompression of SCO compress -H files (LZH, -lh5- method), after gzip's
+// unlzh.c. The data is a sequence of blocks, each starting with its number
+// of codes and three static Huffman tables: one for the lengths of the
+// char/length table, the char/length table itself and the position table.
+// Matches refer to an 8KB window; a block of 0 codes ends the data. Bits
+// are stored MSB first.
+
+use std::io::{self, BufReader, Read, Write};
+
+pub const LZH_MAGIC: u16 = 0xa01f; // 1f a0, as read little-endian
+
+const DICBIT: u32 = 13;
+const DICSIZ: usize = 1 << DICBIT; // window size
+const THRESHOLD: usize = 3; // shortest match
+const MAXMATCH: usize = 256; // longest match
+const NC: usize = 255 + MAXMATCH + 2 - THRESHOLD; // chars and match lengths
+const CBIT: u32 = 9; // bits for the size of the char/length table
+const NP: usize = DICBIT as usize + 1; // position codes
+const PBIT: u32 = 4;
+const NT: usize = 16 + 3; // code length codes
+const TBIT: u32 = 5;
+const MAX_LEN: u8 = 16; // longest code
+
+fn bad_table() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "Bad table")
+}
+
+/// MSB-first bit reader. Like gzip, it reads zeros past the end of the
+/// input, where the final empty block is then found.
+struct BitReader<R> {
+    input: R,
+    bb: u32, // bits, reversed so that the next one is the lowest
+    bk: u32, // number of bits in bb
+}
+
+impl<R: Read> BitReader<R> {
+    fn need(&mut self, n: u32) -> io::Result<()> {
+        while self.bk < n {
+            let mut byte = [0u8; 1];
+            if self.input.read(&mut byte)? == 0 {
+                byte[0] = 0;
+            }
+            self.bb |= (byte[0].reverse_bits() as u32) << self.bk;
+            self.bk += 8;
+        }
+        Ok(())
+    }
+
+    fn get_bits(&mut self, n: u32) -> io::Result<usize> {
+        if n == 0 {
+            return Ok(0);
+        }
+        self.need(n)?;
+        let value = (self.bb & ((1 << n) - 1)).reverse_bits() >> (32 - n);
+        self.bb >>= n;
+        self.bk -= n;
+        Ok(value as usize)
+    }
+}
+
+/// A canonical code: the codes of each length are consecutive, shorter
+/// codes first. As in gzip's make_table, `start[len]` is the first code
+/// of length `len` left-justified to 16 bits, so that the length of the
+/// next code is found by comparing the next 16 bits with these bounds.
+struct Codes {
+    start: [u32; MAX_LEN as usize + 2],
+    offset: [usize; MAX_LEN as usize + 1], // index in `symbols` of the first code of each length
+    symbols: Vec<u16>,                     // symbols by code length, then value
+}
+
+/// A decoding table; a table of a single symbol takes no bits.
+enum Table {
+    Single(usize),
+    Codes(Box<Codes>),
+}
+
+impl Table {
+    /// Builds the table for the given lengths, which must make up a
+    /// complete code, as gzip's make_table requires.
+    fn new(lengths: &[u8]) -> io::Result<Table> {
+        let mut count = [0u32; MAX_LEN as usize + 1];
+        lengths.iter().for_each(|&len| count[len as usize] += 1);
+
+        let mut codes = Codes { start: [0; MAX_LEN as usize + 2], offset: [0; MAX_LEN as usize + 1], symbols: Vec::new() };
+        for (len, &n) in (1..).zip(&count[1..]) {
+            codes.start[len + 1] = codes.start[len] + (n << (MAX_LEN as usize - len));
+            codes.offset[len] = codes.symbols.len();
+            let symbols = (0..lengths.len()).filter(|&symbol| lengths[symbol] as usize == len);
+            codes.symbols.extend(symbols.map(|symbol| symbol as u16));
+        }
+        if codes.start[MAX_LEN as usize + 1] != 1 << MAX_LEN {
+            return Err(bad_table());
+        }
+        Ok(Table::Codes(Box::new(codes)))
+    }
+
+    fn decode<R: Read>(&self, bits: &mut BitReader<R>) -> io::Result<usize> {
+        let codes = match self {
+            Table::Single(symbol) => return Ok(*symbol),
+            Table::Codes(codes) => codes,
+        };
+        bits.need(MAX_LEN as u32)?;
+        let next = (bits.bb & 0xffff).reverse_bits() >> 16;
+        // 第一个上界大于接下来 16 位的码长即为编码长度
+        let len = (1..=MAX_LEN as usize).find(|&len| next < codes.start[len + 1]).expect("the code is complete");
+        let index = codes.offset[len] + ((next - codes.start[len]) >> (MAX_LEN as usize - len)) as usize;
+        bits.bb >>= len;
+        bits.bk -= len as u32;
+        Ok(codes.symbols[index] as usize)
+    }
+}
+
+/// Reads the lengths of a code length or position table (read_pt_len):
+/// lengths below 7 take 3 bits, longer ones continue in unary. With
+/// `special`, the third length is followed by a 2-bit count of zeros.
+fn read_pt_len<R: Read>(bits: &mut BitReader<R>, nn: usize, nbit: u32, special: Option<usize>) -> io::Result<Table> {
+    let n = bits.get_bits(nbit)?;
+    if n == 0 {
+        let symbol = bits.get_bits(nbit)?;
+        return if symbol < nn { Ok(Table::Single(symbol)) } else { Err(bad_table()) };
+    }
+    if n > nn {
+        return Err(bad_table());
+    }
+
+    let mut lengths = vec![0u8; nn];
+    let mut i = 0;
+    while i < n {
+        let mut len = bits.get_bits(3)? as u8;
+        if len == 7 {
+            while bits.get_bits(1)? == 1 {
+                len += 1;
+                if len > MAX_LEN {
+                    return Err(bad_table());
+                }
+            }
+        }
+        lengths[i] = len;
+        i += 1;
+        if Some(i) == special {
+            // 之后若干个码长为 0，保持 lengths 中的 0 即可
+            i += bits.get_bits(2)?;
+            if i > n {
+                return Err(bad_table());
+            }
+        }
+    }
+    Table::new(&lengths)
+}
+
+/// Reads the char/length table (read_c_len), whose lengths are coded with
+/// the code length table: symbols 0 to 2 are runs of zeros, the others a
+/// length plus 2.
+fn read_c_len<R: Read>(bits: &mut BitReader<R>, pt: &Table) -> io::Result<Table> {
+    let n = bits.get_bits(CBIT)?;
+    if n == 0 {
+        let symbol = bits.get_bits(CBIT)?;
+        return if symbol < NC { Ok(Table::Single(symbol)) } else { Err(bad_table()) };
+    }
+    if n > NC {
+        return Err(bad_table());
+    }
+
+    let mut lengths = vec![0u8; NC];
+    let mut i = 0;
+    while i < n {
+        let c = pt.decode(bits)?;
+        let zeros = match c {
+            0 => 1,
+            1 => bits.get_bits(4)? + 3,
+            2 => bits.get_bits(CBIT)? + 20,
+            _ => {
+                lengths[i] = (c - 2) as u8;
+                i += 1;
+                continue;
+            }
+        };
+        i += zeros;
+        if i > n {
+            return Err(bad_table());
+        }
+    }
+    Table::new(&lengths)
+}
+
+/// Decompresses an LZH file from `input` (starting with its magic number)
+/// to `output`.
+pub fn unlzh(input: &mut dyn Read, output: &mut dyn Write) -> io::Result<()> {
+    let mut input = BufReader::new(input);
+    let mut magic = [0u8; 2];
+    input.read_exact(&mut magic)?;
+    if u16::from_le_bytes(magic) != LZH_MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not in lzh format"));
+    }
+    let mut bits = BitReader { input, bb: 0, bk: 0 };
+
+    // 与 gzip 相同，窗口初始为 0，越过数据开头的位置读到 0
+    let mut window = vec![0u8; DICSIZ];
+    let mut pos = 0;
+    let mut out = Vec::with_capacity(0x8000);
+
+    loop {
+        let blocksize = bits.get_bits(16)?;
+        if blocksize == 0 {
+            break;
+        }
+        let pt = read_pt_len(&mut bits, NT, TBIT, Some(3))?;
+        let c_table = read_c_len(&mut bits, &pt)?;
+        let p_table = read_pt_len(&mut bits, NP, PBIT, None)?;
+
+        for _ in 0..blocksize {
+            let c = c_table.decode(&mut bits)?;
+            if c <= 255 {
+                window[pos] = c as u8;
+                pos = (pos + 1) & (DICSIZ - 1);
+                out.push(c as u8);
+            } else {
+                // 匹配：长度 c - 253，距离由位置编码 j 和 j - 1 个附加位给出
+                let len = c - (256 - THRESHOLD);
+                let j = p_table.decode(&mut bits)?;
+                let dist = if j == 0 { 0 } else { (1 << (j - 1)) + bits.get_bits(j as u32 - 1)? };
+                let mut from = pos.wrapping_sub(dist + 1) & (DICSIZ - 1);
+                for _ in 0..len {
+                    let byte = window[from];
+                    window[pos] = byte;
+                    from = (from + 1) & (DICSIZ - 1);
+                    pos = (pos + 1) & (DICSIZ - 1);
+                    out.push(byte);
+                }
+            }
+            if out.len() >= 0x8000 {
+                output.write_all(&out)?;
+                out.clear();
+            }
+        }
+    }
+    output.write_all(&out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_unlzh_vector() {
+        // "abracadabra abracadabra abracadabra\n" in one block, checked
+        // against gzip's unlzh
+        let data = [
+            0x1f, 0xa0, 0x00, 0x0b, 0x38, 0x69, 0x69, 0x45, 0x73, 0xc0, 0x06, 0x05, 0x9e, 0x9d, 0x48, 0x7a, 0x77, 0xaa,
+            0x00, 0x24, 0xe6, 0x78, 0xa2, 0xdb, 0x78, 0x00, 0x00,
+        ];
+        let mut output = Vec::new();
+        unlzh(&mut Cursor::new(&data), &mut output).unwrap();
+        assert_eq!(output, b"abracadabra abracadabra abracadabra\n");
+    }
+
+    #[test]
+    fn test_unlzh_single_symbol_tables() {
+        // 1000 'a' and a newline: overlapping matches at distance 1, so the
+        // position table has a single symbol and takes no bits
+        let data = [
+            0x1f, 0xa0, 0x00, 0x06, 0x30, 0x68, 0x69, 0x7f, 0xb3, 0xc1, 0x0a, 0x2d, 0xc8, 0x04, 0xee, 0x40, 0x00, 0x1e,
+            0x3f, 0x7f, 0x7f, 0x80, 0x00, 0x00,
+        ];
+        let mut output = Vec::new();
+        unlzh(&mut Cursor::new(&data), &mut output).unwrap();
+        let mut expected = vec![b'a'; 1000];
+        expected.push(b'\n');
+        assert_eq!(output, expected);
+
+        // Without the final empty block, the missing bits read as zeros
+        output.clear();
+        unlzh(&mut Cursor::new(&data[..data.len() - 2]), &mut output).unwrap();
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_codes_up_to_16_bits() {
+        // Lengths 1, 2, ..., 16, 16: the codes are 0, 10, 110, ... and the
+        // two 16-bit codes 1111111111111110 and 1111111111111111
+        let lengths: Vec<u8> = (1..=16).chain([16]).collect();
+        let table = Table::new(&lengths).unwrap();
+        let code = |symbol: usize| {
+            let len = lengths[symbol] as usize;
+            (if symbol == 16 { 0xffff } else { (1u32 << len) - 2 }, len)
+        };
+
+        let symbols = [16, 0, 15, 3, 1];
+        let (mut bits, mut nbits) = (0u64, 0);
+        for &symbol in &symbols {
+            let (value, len) = code(symbol);
+            bits = (bits << len) | value as u64;
+            nbits += len;
+        }
+        let bytes = (bits << (64 - nbits)).to_be_bytes();
+        let mut reader = BitReader { input: &bytes[..], bb: 0, bk: 0 };
+        for &symbol in &symbols {
+            assert_eq!(table.decode(&mut reader).unwrap(), symbol);
+        }
+    }
+
+    #[test]
+    fn test_unlzh_errors() {
+        // A code length table of a single length-1 code is incomplete
+        let data = [0x1f, 0xa0, 0x00, 0x01, 0x09, 0x00];
+        let err = unlzh(&mut Cursor::new(&data), &mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "Bad table");
+
+        let err = unlzh(&mut Cursor::new(&[0x1f, 0x9d, 0x90]), &mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "not in lzh format");
+    }
+}
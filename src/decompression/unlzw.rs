@@ -0,0 +1,281 @@
+// src/decompression/unlzw.rs
+//
+// Decompression of files created by compress(1) (.Z), after gzip's
+// unlzw.c. The codes are 9 to `maxbits` bits wide, LSB first, written in
+// groups of eight codes (n_bits bytes); when the code width changes, the
+// rest of the current group is padding.
+
+use std::io::{self, Read, Write};
+
+pub const LZW_MAGIC: u16 = 0x9d1f; // 1f 9d, as read little-endian
+
+const BIT_MASK: u8 = 0x1f; // mask for the maximum number of bits
+const BLOCK_MODE: u8 = 0x80; // CLEAR code in use
+const LZW_RESERVED: u8 = 0x60; // reserved flag bits
+const INIT_BITS: u32 = 9;
+const BITS: u32 = 16; // largest code width handled
+const CLEAR: usize = 256; // flush the dictionary
+const FIRST: usize = 257; // first free entry in block mode
+
+fn corrupt() -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, "corrupt input.")
+}
+
+/// Reads the codes of one group at a time, so that the padding at the end
+/// of a group can be dropped when the code width changes.
+struct CodeReader {
+    group: [u8; BITS as usize],
+    len: usize,      // bytes in the current group
+    pos: usize,      // bit position in the current group
+    n_bits: u32,     // current code width
+    eof: bool,       // whether the input has ended
+}
+
+impl CodeReader {
+    /// Returns the next code, or None at the end of the input (fewer than
+    /// n_bits bits left).
+    fn next_code(&mut self, input: &mut dyn Read) -> io::Result<Option<usize>> {
+        if self.pos + self.n_bits as usize > self.len * 8 {
+            if self.eof {
+                return Ok(None);
+            }
+            // 读入下一组 n_bits 个字节（8 个编码）
+            let size = self.n_bits as usize;
+            self.len = 0;
+            while self.len < size {
+                let n = input.read(&mut self.group[self.len..size])?;
+                if n == 0 {
+                    self.eof = true;
+                    break;
+                }
+                self.len += n;
+            }
+            self.pos = 0;
+            if self.n_bits as usize > self.len * 8 {
+                return Ok(None);
+            }
+        }
+
+        let byte = self.pos / 8;
+        let mut word = 0u32;
+        for (i, &b) in self.group[byte..self.len.min(byte + 3)].iter().enumerate() {
+            word |= (b as u32) << (8 * i);
+        }
+        self.pos += self.n_bits as usize;
+        Ok(Some(((word >> (self.pos - self.n_bits as usize - 8 * byte)) & ((1 << self.n_bits) - 1)) as usize))
+    }
+
+    /// Changes the code width, skipping the rest of the current group.
+    fn set_bits(&mut self, n_bits: u32) {
+        self.n_bits = n_bits;
+        self.pos = self.len * 8;
+    }
+}
+
+/// Decompresses a .Z file from `input` (starting with its 3 byte header)
+/// to `output`.
+pub fn unlzw(input: &mut dyn Read, output: &mut dyn Write) -> io::Result<()> {
+    let mut header = [0u8; 3];
+    input.read_exact(&mut header)?;
+    if u16::from_le_bytes([header[0], header[1]]) != LZW_MAGIC {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "not in compress format"));
+    }
+    let flags = header[2];
+    if flags & LZW_RESERVED != 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("unknown flags 0x{:x}", flags & LZW_RESERVED),
+        ));
+    }
+    let maxbits = (flags & BIT_MASK) as u32;
+    let block_mode = flags & BLOCK_MODE != 0;
+    if !(INIT_BITS..=BITS).contains(&maxbits) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("compressed with {} bits, can only handle {} bits", maxbits, BITS),
+        ));
+    }
+    let maxmaxcode = 1usize << maxbits;
+
+    let mut prefix = vec![0u16; maxmaxcode];
+    let mut suffix = vec![0u8; maxmaxcode];
+    let mut stack = Vec::new();
+    let mut out = Vec::with_capacity(0x8000);
+
+    let mut codes = CodeReader { group: [0; BITS as usize], len: 0, pos: 0, n_bits: INIT_BITS, eof: false };
+    let mut maxcode = (1 << INIT_BITS) - 1;
+    let mut free_ent = if block_mode { FIRST } else { CLEAR };
+    let mut oldcode: Option<usize> = None;
+    let mut finchar = 0u8;
+
+    loop {
+        if free_ent > maxcode {
+            // 编码位数增加，剩余的组被跳过；与 compress 相同，-b 9 时也会增加到 10 位
+            codes.set_bits(codes.n_bits + 1);
+            maxcode = if codes.n_bits == maxbits { maxmaxcode } else { (1 << codes.n_bits) - 1 };
+        }
+        let Some(mut code) = codes.next_code(input)? else { break };
+
+        let Some(old) = oldcode else {
+            // 第一个编码必须是字面值
+            if code >= 256 {
+                return Err(corrupt());
+            }
+            finchar = code as u8;
+            oldcode = Some(code);
+            out.push(finchar);
+            continue;
+        };
+
+        if code == CLEAR && block_mode {
+            // 清空字典，从 9 位编码重新开始；256 这一项随后被无用的值占据
+            free_ent = FIRST - 1;
+            codes.set_bits(INIT_BITS);
+            maxcode = (1 << INIT_BITS) - 1;
+            continue;
+        }
+
+        let incode = code;
+        if code >= free_ent {
+            // 特殊情况 KwKwK：编码正是将要加入的那一项
+            if code > free_ent {
+                return Err(corrupt());
+            }
+            stack.push(finchar);
+            code = old;
+        }
+        while code >= 256 {
+            stack.push(suffix[code]);
+            code = prefix[code] as usize;
+        }
+        finchar = code as u8;
+        stack.push(finchar);
+        out.extend(stack.drain(..).rev());
+
+        if free_ent < maxmaxcode {
+            prefix[free_ent] = old as u16;
+            suffix[free_ent] = finchar;
+            free_ent += 1;
+        }
+        oldcode = Some(incode);
+
+        if out.len() >= 0x8000 {
+            output.write_all(&out)?;
+            out.clear();
+        }
+    }
+    output.write_all(&out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use std::io::Cursor;
+
+    /// Compresses like compress(1) with `-b maxbits`, clearing the
+    /// dictionary whenever it is full.
+    fn lzw(data: &[u8], maxbits: u32) -> Vec<u8> {
+        let mut out = vec![0x1f, 0x9d, BLOCK_MODE | maxbits as u8];
+        let maxmaxcode = 1usize << maxbits;
+        let mut dict: HashMap<(usize, u8), usize> = HashMap::new();
+        let (mut n_bits, mut maxcode, mut free_ent) = (INIT_BITS, 511usize, FIRST);
+        let mut group: Vec<u8> = Vec::new();
+        let mut bits = 0u32;
+        let mut nbuf = 0u32;
+
+        let mut emit = |code: usize, n_bits: u32, pad: bool, group: &mut Vec<u8>, out: &mut Vec<u8>| {
+            bits |= (code as u32) << nbuf;
+            nbuf += n_bits;
+            while nbuf >= 8 {
+                group.push(bits as u8);
+                bits >>= 8;
+                nbuf -= 8;
+            }
+            if pad || group.len() == n_bits as usize && nbuf == 0 {
+                if nbuf > 0 {
+                    group.push(bits as u8);
+                    bits = 0;
+                    nbuf = 0;
+                }
+                if pad {
+                    group.resize(n_bits as usize, 0);
+                }
+                out.append(group);
+            }
+        };
+
+        let Some((&first, rest)) = data.split_first() else { return out };
+        let mut ent = first as usize;
+        for &c in rest {
+            if let Some(&code) = dict.get(&(ent, c)) {
+                ent = code;
+                continue;
+            }
+            let grow = free_ent > maxcode;
+            emit(ent, n_bits, grow, &mut group, &mut out);
+            if grow {
+                n_bits += 1;
+                maxcode = if n_bits == maxbits { maxmaxcode } else { (1 << n_bits) - 1 };
+            }
+            if free_ent < maxmaxcode {
+                dict.insert((ent, c), free_ent);
+                free_ent += 1;
+            } else {
+                emit(CLEAR, n_bits, true, &mut group, &mut out);
+                dict.clear();
+                free_ent = FIRST;
+                n_bits = INIT_BITS;
+                maxcode = 511;
+            }
+            ent = c as usize;
+        }
+        emit(ent, n_bits, false, &mut group, &mut out);
+        if nbuf > 0 {
+            group.push(bits as u8);
+        }
+        out.append(&mut group);
+        out
+    }
+
+    #[test]
+    fn test_unlzw_vector() {
+        // "TOBEORNOTTOBEORTOBEORNOT" as compress writes it
+        let data = [
+            0x1f, 0x9d, 0x90, 0x54, 0x9e, 0x08, 0x29, 0xf2, 0x44, 0x8a, 0x93, 0x27, 0x54, 0x02, 0x0e, 0x2c, 0xa8, 0x90,
+            0xa0, 0x41, 0x84,
+        ];
+        let mut output = Vec::new();
+        unlzw(&mut Cursor::new(&data), &mut output).unwrap();
+        assert_eq!(output, b"TOBEORNOTTOBEORTOBEORNOT");
+    }
+
+    #[test]
+    fn test_unlzw_code_widths_and_clear() {
+        let mut seed = 7u32;
+        let data: Vec<u8> = (0..300_000)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+                b"abcdefghij"[(seed >> 16) as usize % 10]
+            })
+            .collect();
+        // 9 bits: the dictionary is cleared over and over (the codes still
+        // grow to 10 bits, as in compress); 16 bits: the width grows all
+        // the way
+        for maxbits in [9, 12, 16] {
+            let mut output = Vec::new();
+            unlzw(&mut Cursor::new(lzw(&data, maxbits)), &mut output).unwrap();
+            assert_eq!(output, data, "maxbits {}", maxbits);
+        }
+    }
+
+    #[test]
+    fn test_unlzw_errors() {
+        let mut output = Vec::new();
+        let err = unlzw(&mut Cursor::new(&[0x1f, 0x9d, 0x91, 0, 0]), &mut output).unwrap_err();
+        assert_eq!(err.to_string(), "compressed with 17 bits, can only handle 16 bits");
+        // A first code that is not a literal
+        let err = unlzw(&mut Cursor::new(&[0x1f, 0x9d, 0x90, 0xff, 0x01]), &mut output).unwrap_err();
+        assert_eq!(err.to_string(), "corrupt input.");
+    }
+}
@@ -0,0 +1,249 @@
+// src/decompression/unpack.rs
+//
+// Decompression of files created by pack(1) (.z), after gzip's unpack.c.
+// The header gives the number of Huffman leaves at each bit length and the
+// byte values of the leaves; the end of the data is marked by an EOB code,
+// the last code of the longest length.
+
+use std::io::{self, BufRead, BufReader, Read, Write};
+
+use crate::decompression::huft::build_prefix_table;
+
+pub const PACK_MAGIC: u16 = 0x1e1f; // 1f 1e, as read little-endian
+
+const LITERALS: usize = 256; // number of literals, excluding EOB
+const MAX_BITLEN: usize = 25; // longest code
+const MAX_PEEK: usize = 12; // bits of the prefix table for the short codes
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+fn get_byte(input: &mut dyn BufRead) -> io::Result<u8> {
+    let mut byte = [0u8; 1];
+    input.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+/// MSB-first bit reader (unpack.c's look_bits and skip_bits). Codes near
+/// the end may be looked at with more bits than remain; those read as
+/// zeros, but using them is an error.
+struct BitReader<'a> {
+    input: &'a mut dyn BufRead,
+    bitbuf: u64,
+    valid: usize,  // number of bits in bitbuf
+    padding: usize, // zero bits added past the end of the input
+}
+
+impl BitReader<'_> {
+    fn look_bits(&mut self, bits: usize) -> io::Result<usize> {
+        while self.valid < bits {
+            let byte = match get_byte(self.input) {
+                Ok(byte) => byte,
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    self.padding += 8;
+                    0
+                }
+                Err(e) => return Err(e),
+            };
+            self.bitbuf = (self.bitbuf << 8) | byte as u64;
+            self.valid += 8;
+        }
+        Ok(((self.bitbuf >> (self.valid - bits)) & ((1 << bits) - 1)) as usize)
+    }
+
+    fn skip_bits(&mut self, bits: usize) -> io::Result<()> {
+        self.valid -= bits;
+        if self.valid < self.padding {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of file"));
+        }
+        Ok(())
+    }
+}
+
+/// Decompresses a packed file from `input` (starting with its magic
+/// number) to `output`.
+pub fn unpack(input: &mut dyn Read, output: &mut dyn Write) -> io::Result<()> {
+    let mut input = BufReader::new(input);
+    let mut header = [0u8; 7];
+    input.read_exact(&mut header)?;
+    if u16::from_le_bytes([header[0], header[1]]) != PACK_MAGIC {
+        return Err(invalid("not in pack format"));
+    }
+    // Original length, most significant byte first
+    let orig_len = u32::from_be_bytes([header[2], header[3], header[4], header[5]]);
+    let max_len = header[6] as usize;
+    if !(1..=MAX_BITLEN).contains(&max_len) {
+        return Err(invalid("invalid compressed data -- Huffman code bit length out of range"));
+    }
+
+    // Leaves at each bit length; the count for the longest length is
+    // stored less 2, one of them being EOB
+    let mut leaves = [0usize; MAX_BITLEN + 1];
+    let mut max_leaves = 1;
+    let mut n = 0;
+    for (len, count) in (1..).zip(&mut leaves[1..=max_len]) {
+        *count = get_byte(&mut input)? as usize;
+        if max_leaves - usize::from(len == max_len) < *count {
+            return Err(invalid("too many leaves in Huffman tree"));
+        }
+        max_leaves = (max_leaves - *count + 1) * 2 - 1;
+        n += *count;
+    }
+    if n >= LITERALS {
+        return Err(invalid("too many leaves in Huffman tree"));
+    }
+    leaves[max_len] += 1;
+
+    // Byte values of the leaves by bit length; lit_base[len] is the index
+    // of the first leaf of length len
+    let mut literal = Vec::with_capacity(n + 1);
+    let mut lit_base = [0usize; MAX_BITLEN + 1];
+    for (len, &count) in (1..).zip(&leaves[1..=max_len]) {
+        lit_base[len] = literal.len();
+        for _ in 0..count {
+            literal.push(get_byte(&mut input)?);
+        }
+    }
+    leaves[max_len] += 1; // EOB
+    let kraft: u64 = (1..).zip(&leaves[1..=max_len]).map(|(len, &count)| (count as u64) << (max_len - len)).sum();
+    if kraft != 1 << max_len {
+        return Err(invalid("too few leaves in Huffman tree"));
+    }
+
+    let table = build_prefix_table(&leaves[..=max_len], MAX_PEEK);
+    let peek_bits = table.peek_bits();
+    let eob = leaves[max_len] - 1; // the last code of the longest length
+
+    let mut bits = BitReader { input: &mut input, bitbuf: 0, valid: 0, padding: 0 };
+    let mut out = Vec::with_capacity(0x8000);
+    let mut size = 0u64;
+    loop {
+        let mut peek = bits.look_bits(peek_bits)?;
+        let mut len = table.prefix_len(peek);
+        if len > 0 {
+            peek >>= peek_bits - len;
+        } else {
+            // A longer code: add bits until it is no longer an internal node
+            len = peek_bits;
+            loop {
+                len += 1;
+                peek = bits.look_bits(len)?;
+                if peek >= table.parents(len) {
+                    break;
+                }
+            }
+        }
+        bits.skip_bits(len)?;
+        if len == max_len && peek == eob {
+            break;
+        }
+        out.push(literal[peek - table.parents(len) + lit_base[len]]);
+        if out.len() == 0x8000 {
+            output.write_all(&out)?;
+            size += out.len() as u64;
+            out.clear();
+        }
+    }
+    output.write_all(&out)?;
+    size += out.len() as u64;
+
+    if size as u32 != orig_len {
+        return Err(invalid("invalid compressed data--length error"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    /// Packs `data` with the code whose leaves at each length (from 1) are
+    /// given by `levels`, the last level without the EOB.
+    fn pack(data: &[u8], levels: &[Vec<u8>]) -> Vec<u8> {
+        let max_len = levels.len();
+        let mut out = vec![0x1f, 0x1e];
+        out.extend_from_slice(&(data.len() as u32).to_be_bytes());
+        out.push(max_len as u8);
+        for (i, level) in levels.iter().enumerate() {
+            out.push((level.len() - usize::from(i + 1 == max_len)) as u8);
+        }
+        levels.iter().for_each(|level| out.extend_from_slice(level));
+
+        // Internal nodes of each length, whose codes come before the leaves
+        let mut parents = vec![0u32; max_len + 1];
+        let mut nodes = 0;
+        for len in (1..=max_len).rev() {
+            nodes >>= 1;
+            parents[len] = nodes;
+            nodes += levels[len - 1].len() as u32 + u32::from(len == max_len);
+        }
+        let code_of = |symbol: Option<u8>| {
+            for (i, level) in levels.iter().enumerate() {
+                let index = match symbol {
+                    Some(byte) => level.iter().position(|&b| b == byte),
+                    None if i + 1 == max_len => Some(level.len()),
+                    None => None,
+                };
+                if let Some(index) = index {
+                    return (parents[i + 1] + index as u32, i + 1);
+                }
+            }
+            panic!("no code for {:?}", symbol);
+        };
+
+        let (mut bits, mut nbits) = (0u64, 0);
+        for symbol in data.iter().map(|&b| Some(b)).chain([None]) {
+            let (code, len) = code_of(symbol);
+            bits = (bits << len) | code as u64;
+            nbits += len;
+            while nbits >= 8 {
+                nbits -= 8;
+                out.push((bits >> nbits) as u8);
+            }
+        }
+        if nbits > 0 {
+            out.push((bits << (8 - nbits)) as u8);
+        }
+        out
+    }
+
+    #[test]
+    fn test_unpack_vector() {
+        // "abracadabra" as pack writes it
+        let data = [
+            0x1f, 0x1e, 0x00, 0x00, 0x00, 0x0b, 0x04, 0x01, 0x00, 0x03, 0x00, 0x61, 0x62, 0x72, 0x64, 0x63, 0x95, 0x0b,
+            0x95, 0x10,
+        ];
+        let mut output = Vec::new();
+        unpack(&mut Cursor::new(&data), &mut output).unwrap();
+        assert_eq!(output, b"abracadabra");
+        assert_eq!(pack(b"abracadabra", &[vec![b'a'], vec![], vec![b'b', b'r', b'd'], vec![b'c']]), data);
+    }
+
+    #[test]
+    fn test_unpack_long_codes() {
+        // One leaf per length, up to 25 bits: past the prefix table
+        let levels: Vec<Vec<u8>> = (0..25).map(|i| vec![b'a' + i]).collect();
+        let data: Vec<u8> = (0..25u8).flat_map(|i| std::iter::repeat_n(b'a' + i, 1 + i as usize * 3)).collect();
+        let mut output = Vec::new();
+        unpack(&mut Cursor::new(pack(&data, &levels)), &mut output).unwrap();
+        assert_eq!(output, data);
+    }
+
+    #[test]
+    fn test_unpack_errors() {
+        let mut packed = pack(b"mississippi", &[vec![b's'], vec![b'i'], vec![b'p'], vec![b'm']]);
+        packed[5] += 1;
+        let err = unpack(&mut Cursor::new(&packed), &mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "invalid compressed data--length error");
+        // Cut before the EOB code
+        let err = unpack(&mut Cursor::new(&packed[..packed.len() - 1]), &mut Vec::new()).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+
+        // Three leaves of length 1
+        let err = unpack(&mut Cursor::new(&[0x1f, 0x1e, 0, 0, 0, 1, 1, 1, b'a', b'b']), &mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "too many leaves in Huffman tree");
+    }
+}
@@ -0,0 +1,229 @@
+// src/decompression/unzip.rs
+//
+// Decompression of the first entry of a PKZIP archive, like gzip's
+// unzip.c: the entry must be stored or deflated, and its CRC and length,
+// taken from the local header or from the data descriptor that follows
+// the data (flag bit 3), are checked. The rest of the archive is ignored.
+
+use std::io::{self, BufRead, Read, Write};
+
+use crate::decompression::inflate::inflate;
+use crate::util::crc::updcrc;
+
+pub const LOCAL_HEADER_MAGIC: [u8; 4] = *b"PK\x03\x04"; // LOCSIG
+const DESCRIPTOR_MAGIC: [u8; 4] = *b"PK\x07\x08"; // EXTSIG
+const LOCAL_HEADER_SIZE: usize = 30; // LOCHDR
+
+const FLAG_ENCRYPTED: u16 = 0x0001; // CRPFLG
+const FLAG_DESCRIPTOR: u16 = 0x0008; // EXTFLG
+
+pub const METHOD_STORED: u16 = 0;
+pub const METHOD_DEFLATED: u16 = 8;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// The fields of a local file header that matter for extracting it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ZipHeader {
+    pub name: Vec<u8>,
+    pub flags: u16,
+    pub method: u16,
+    /// CRC, compressed and uncompressed sizes; zero when they are given by
+    /// the data descriptor instead.
+    pub crc: u32,
+    pub compressed_size: u32,
+    pub size: u32,
+}
+
+impl ZipHeader {
+    /// Whether the CRC and sizes follow the data (flag bit 3).
+    pub fn has_descriptor(&self) -> bool {
+        self.flags & FLAG_DESCRIPTOR != 0
+    }
+}
+
+/// Reads and checks the local header of the first entry, leaving `input`
+/// positioned at its data.
+pub fn read_zip_header(input: &mut dyn Read) -> io::Result<ZipHeader> {
+    let mut h = [0u8; LOCAL_HEADER_SIZE];
+    input.read_exact(&mut h)?;
+    if h[..4] != LOCAL_HEADER_MAGIC {
+        return Err(invalid("not a valid zip file"));
+    }
+    let u16_at = |i: usize| u16::from_le_bytes([h[i], h[i + 1]]);
+    let u32_at = |i: usize| u32::from_le_bytes([h[i], h[i + 1], h[i + 2], h[i + 3]]);
+
+    let header = ZipHeader {
+        name: Vec::new(),
+        flags: u16_at(6),
+        method: u16_at(8),
+        crc: u32_at(14),
+        compressed_size: u32_at(18),
+        size: u32_at(22),
+    };
+    if header.method != METHOD_STORED && header.method != METHOD_DEFLATED {
+        return Err(invalid("first entry not deflated or stored -- use unzip"));
+    }
+    if header.flags & FLAG_ENCRYPTED != 0 {
+        return Err(invalid("encrypted file -- use unzip"));
+    }
+    if header.method == METHOD_STORED && header.has_descriptor() {
+        // 存储的数据没有结束标记，必须在头部给出长度
+        return Err(invalid("stored entry with a data descriptor -- use unzip"));
+    }
+
+    // 文件名与扩展字段
+    let mut name = vec![0u8; u16_at(26) as usize];
+    input.read_exact(&mut name)?;
+    io::copy(&mut input.take(u16_at(28) as u64), &mut io::sink())?;
+    Ok(ZipHeader { name, ..header })
+}
+
+/// Passes the data through while keeping its CRC and length.
+struct CrcWriter<'a> {
+    inner: &'a mut dyn Write,
+    crc: u32,
+    size: u64,
+}
+
+impl Write for CrcWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.crc = updcrc(self.crc, &buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Extracts the first entry of the zip archive read from `input` to
+/// `output`, and returns its header.
+pub fn unzip(input: &mut dyn BufRead, output: &mut dyn Write) -> io::Result<ZipHeader> {
+    let header = read_zip_header(input)?;
+    let mut output = CrcWriter { inner: output, crc: 0, size: 0 };
+
+    if header.method == METHOD_DEFLATED {
+        inflate(input, &mut output)?;
+    } else {
+        if header.compressed_size != header.size {
+            return Err(invalid("invalid compressed data--length mismatch"));
+        }
+        let copied = io::copy(&mut input.take(header.size as u64), &mut output)?;
+        if copied != header.size as u64 {
+            return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "unexpected end of file"));
+        }
+    }
+
+    let (crc, size) = if header.has_descriptor() {
+        // 数据描述符：可选的签名，然后是 CRC、压缩后与压缩前的长度
+        let mut descriptor = [0u8; 16];
+        input.read_exact(&mut descriptor[..12])?;
+        if descriptor[..4] == DESCRIPTOR_MAGIC {
+            input.read_exact(&mut descriptor[12..])?;
+            descriptor.copy_within(4.., 0);
+        }
+        let u32_at = |i: usize| u32::from_le_bytes([descriptor[i], descriptor[i + 1], descriptor[i + 2], descriptor[i + 3]]);
+        (u32_at(0), u32_at(8))
+    } else {
+        (header.crc, header.size)
+    };
+
+    if crc != output.crc {
+        return Err(invalid("invalid compressed data--crc error"));
+    }
+    if size != output.size as u32 {
+        return Err(invalid("invalid compressed data--length error"));
+    }
+    Ok(header)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::{deflate_raw, DeflateOptions};
+    use std::io::Cursor;
+
+    /// A local header, the data and, with `descriptor`, a data descriptor
+    /// without its optional signature.
+    fn zip_entry(data: &[u8], method: u16, descriptor: bool) -> Vec<u8> {
+        let mut body = Vec::new();
+        if method == METHOD_DEFLATED {
+            deflate_raw(&mut Cursor::new(data), &mut body, &DeflateOptions::default()).unwrap();
+        } else {
+            body.extend_from_slice(data);
+        }
+        let crc = updcrc(0, data);
+        let sizes = [crc, body.len() as u32, data.len() as u32];
+
+        let mut entry = LOCAL_HEADER_MAGIC.to_vec();
+        entry.extend_from_slice(&20u16.to_le_bytes());
+        entry.extend_from_slice(&(if descriptor { FLAG_DESCRIPTOR } else { 0 }).to_le_bytes());
+        entry.extend_from_slice(&method.to_le_bytes());
+        entry.extend_from_slice(&[0; 4]); // time and date
+        for value in sizes {
+            entry.extend_from_slice(&(if descriptor { 0 } else { value }).to_le_bytes());
+        }
+        entry.extend_from_slice(&8u16.to_le_bytes());
+        entry.extend_from_slice(&4u16.to_le_bytes());
+        entry.extend_from_slice(b"data.txt");
+        entry.extend_from_slice(&[0xaa; 4]);
+        entry.extend_from_slice(&body);
+        if descriptor {
+            sizes.iter().for_each(|value| entry.extend_from_slice(&value.to_le_bytes()));
+        }
+        entry
+    }
+
+    #[test]
+    fn test_unzip_vector_with_descriptor() {
+        // zipfile writing "hello hello hello\n" to a pipe: deflated, with
+        // a signed data descriptor and the central directory after it
+        let archive = [
+            0x50, 0x4b, 0x03, 0x04, 0x14, 0x00, 0x08, 0x00, 0x08, 0x00, 0x83, 0x18, 0x22, 0x58, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x06, 0x00, 0x00, 0x00, 0x68, 0x69, 0x2e, 0x74, 0x78, 0x74,
+            0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x57, 0xc8, 0x40, 0x90, 0x5c, 0x00, 0x50, 0x4b, 0x07, 0x08, 0x3b, 0x7c, 0x8a,
+            0xdf, 0x0b, 0x00, 0x00, 0x00, 0x12, 0x00, 0x00, 0x00, 0x50, 0x4b, 0x01, 0x02, 0x14, 0x03, 0x14, 0x00, 0x08,
+            0x00, 0x08, 0x00, 0x83, 0x18, 0x22, 0x58, 0x3b, 0x7c, 0x8a, 0xdf, 0x0b, 0x00, 0x00, 0x00, 0x12, 0x00, 0x00,
+            0x00, 0x06, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x80, 0x01, 0x00, 0x00, 0x00,
+            0x00, 0x68, 0x69, 0x2e, 0x74, 0x78, 0x74, 0x50, 0x4b, 0x05, 0x06, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x01,
+            0x00, 0x34, 0x00, 0x00, 0x00, 0x3f, 0x00, 0x00, 0x00, 0x00, 0x00,
+        ];
+        let mut output = Vec::new();
+        let header = unzip(&mut Cursor::new(&archive), &mut output).unwrap();
+        assert_eq!(output, b"hello hello hello\n");
+        assert_eq!(header.name, b"hi.txt");
+        assert!(header.has_descriptor());
+    }
+
+    #[test]
+    fn test_unzip_methods_and_checks() {
+        let data = b"zip entries are either stored or deflated\n".repeat(40);
+        for (method, descriptor) in [(METHOD_STORED, false), (METHOD_DEFLATED, false), (METHOD_DEFLATED, true)] {
+            let mut output = Vec::new();
+            let header = unzip(&mut Cursor::new(zip_entry(&data, method, descriptor)), &mut output).unwrap();
+            assert_eq!(output, data);
+            assert_eq!((header.method, header.name.as_slice()), (method, &b"data.txt"[..]));
+        }
+
+        // A corrupted CRC in the header, then in the data descriptor
+        let mut entry = zip_entry(&data, METHOD_STORED, false);
+        entry[14] ^= 1;
+        let err = unzip(&mut Cursor::new(&entry), &mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "invalid compressed data--crc error");
+        let mut entry = zip_entry(&data, METHOD_DEFLATED, true);
+        let size_at = entry.len() - 1;
+        entry[size_at] ^= 1;
+        let err = unzip(&mut Cursor::new(&entry), &mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "invalid compressed data--length error");
+
+        let mut entry = zip_entry(&data, METHOD_STORED, false);
+        entry[8] = 12; // bzip2
+        let err = unzip(&mut Cursor::new(&entry), &mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "first entry not deflated or stored -- use unzip");
+    }
+}
@@ -0,0 +1,204 @@
+// src/decompression/zlib.rs
+//
+// Reading zlib streams (RFC 1950): the header is checked when the decoder
+// is created, and the Adler-32 trailer once all the data has been read.
+
+use std::io::{self, BufRead, BufReader, Read};
+
+use crate::compression::zlib::{ZlibHeader, ZLIB_FLAG_FDICT, ZLIB_METHOD_DEFLATE};
+use crate::decompression::inflate::InflateState;
+use crate::util::adler32::adler32;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, msg.to_string())
+}
+
+/// Reads and checks a zlib header, leaving `input` positioned at the start
+/// of the deflate data.
+pub fn read_zlib_header(input: &mut dyn Read) -> io::Result<ZlibHeader> {
+    let mut fixed = [0u8; 2];
+    input.read_exact(&mut fixed)?;
+    let (cmf, flg) = (fixed[0], fixed[1]);
+
+    if !(cmf as u16 * 256 + flg as u16).is_multiple_of(31) {
+        return Err(invalid("incorrect header check"));
+    }
+    if cmf & 0x0f != ZLIB_METHOD_DEFLATE {
+        return Err(invalid("unknown compression method"));
+    }
+    let window_bits = (cmf >> 4) + 8;
+    if window_bits > 15 {
+        return Err(invalid("invalid window size"));
+    }
+
+    let dict_id = if flg & ZLIB_FLAG_FDICT != 0 {
+        let mut id = [0u8; 4];
+        input.read_exact(&mut id)?;
+        Some(u32::from_be_bytes(id))
+    } else {
+        None
+    };
+
+    Ok(ZlibHeader { window_bits, level: flg >> 6, dict_id })
+}
+
+/// A zlib decompressor implementing `Read` and `BufRead`, the zlib
+/// counterpart of `GzDecoder`.
+pub struct ZlibDecoder<R: Read> {
+    input: BufReader<R>,
+    header: ZlibHeader,
+    inflate: InflateState,
+    adler: u32,    // Adler-32 of the bytes consumed so far
+    checked: bool, // whether the trailer has been read and checked
+}
+
+impl<R: Read> ZlibDecoder<R> {
+    /// Reads the zlib header from `inner` and returns a decoder for the
+    /// data that follows.
+    pub fn new(inner: R) -> io::Result<Self> {
+        let decoder = ZlibDecoder::open(inner)?;
+        if decoder.header.dict_id.is_some() {
+            return Err(invalid("need dictionary"));
+        }
+        Ok(decoder)
+    }
+
+    /// Like `new`, for streams compressed with a preset dictionary: the
+    /// dictionary id in the header must be the Adler-32 of `dictionary`.
+    /// A stream without a dictionary id is decoded without it.
+    pub fn with_dictionary(inner: R, dictionary: &[u8]) -> io::Result<Self> {
+        let mut decoder = ZlibDecoder::open(inner)?;
+        if let Some(dict_id) = decoder.header.dict_id {
+            if dict_id != adler32(1, dictionary) {
+                return Err(invalid("incorrect dictionary"));
+            }
+            decoder.inflate.set_dictionary(dictionary)?;
+        }
+        Ok(decoder)
+    }
+
+    fn open(inner: R) -> io::Result<Self> {
+        let mut input = BufReader::new(inner);
+        let header = read_zlib_header(&mut input)?;
+        Ok(ZlibDecoder {
+            input,
+            header,
+            inflate: InflateState::new(),
+            adler: 1,
+            checked: false,
+        })
+    }
+
+    /// The header of the zlib stream.
+    pub fn header(&self) -> &ZlibHeader {
+        &self.header
+    }
+
+    /// The underlying reader.
+    pub fn get_ref(&self) -> &R {
+        self.input.get_ref()
+    }
+
+    fn check_trailer(&mut self) -> io::Result<()> {
+        let mut trailer = [0u8; 4];
+        self.input.read_exact(&mut trailer)?;
+        if u32::from_be_bytes(trailer) != self.adler {
+            return Err(invalid("incorrect data check"));
+        }
+        self.checked = true;
+        Ok(())
+    }
+}
+
+impl<R: Read> BufRead for ZlibDecoder<R> {
+    fn fill_buf(&mut self) -> io::Result<&[u8]> {
+        if self.inflate.fill_buf(&mut self.input)?.is_empty() && !self.checked {
+            self.check_trailer()?;
+        }
+        self.inflate.fill_buf(&mut self.input)
+    }
+
+    fn consume(&mut self, amt: usize) {
+        let pending = self.inflate.pending();
+        let amt = amt.min(pending.len());
+        self.adler = adler32(self.adler, &pending[..amt]);
+        self.inflate.consume(amt);
+    }
+}
+
+impl<R: Read> Read for ZlibDecoder<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let pending = self.fill_buf()?;
+        let n = pending.len().min(buf.len());
+        buf[..n].copy_from_slice(&pending[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compression::{CompressionLevel, DeflateOptions, ZlibEncoder};
+    use std::io::{Cursor, Write};
+
+    #[test]
+    fn test_zlib_roundtrip() {
+        let data: Vec<u8> = (0..40_000u32).flat_map(|i| format!("{} ", i % 613).into_bytes()).collect();
+        for level in [1, 6, 9] {
+            let options = DeflateOptions { level: CompressionLevel::new(level).unwrap() };
+            let mut encoder = ZlibEncoder::new(Vec::new(), &options);
+            encoder.write_all(&data).unwrap();
+            let compressed = encoder.finish().unwrap();
+
+            let mut decoder = ZlibDecoder::new(Cursor::new(&compressed)).unwrap();
+            assert_eq!(decoder.header(), &ZlibHeader::new(options.level));
+            let mut output = Vec::new();
+            decoder.read_to_end(&mut output).unwrap();
+            assert_eq!(output, data);
+        }
+    }
+
+    #[test]
+    fn test_zlib_decoder_checks_header_and_trailer() {
+        // zlib.compress(b"hello", 9)
+        let mut compressed = vec![0x78, 0xda, 0xcb, 0x48, 0xcd, 0xc9, 0xc9, 0x07, 0x00, 0x06, 0x2c, 0x02, 0x15];
+        let mut output = String::new();
+        ZlibDecoder::new(Cursor::new(&compressed)).unwrap().read_to_string(&mut output).unwrap();
+        assert_eq!(output, "hello");
+
+        let last = compressed.len() - 1;
+        compressed[last] ^= 1;
+        let err = ZlibDecoder::new(Cursor::new(&compressed)).unwrap().read_to_end(&mut Vec::new()).unwrap_err();
+        assert_eq!(err.to_string(), "incorrect data check");
+
+        let err = ZlibDecoder::new(Cursor::new(&[0x78, 0x9d])).err().unwrap();
+        assert_eq!(err.to_string(), "incorrect header check");
+
+        let header = read_zlib_header(&mut Cursor::new(&[0x78, 0xbb, 0x12, 0x34, 0x56, 0x78])).unwrap();
+        assert_eq!(header.dict_id, Some(0x1234_5678));
+        assert!(ZlibDecoder::new(Cursor::new(&[0x78, 0xbb, 0x12, 0x34, 0x56, 0x78])).is_err());
+    }
+
+    #[test]
+    fn test_zlib_preset_dictionary() {
+        let dictionary = b"GET / HTTP/1.1\r\nHost: \r\nAccept-Encoding: gzip, deflate\r\nUser-Agent: ";
+        let request = b"GET /index.html HTTP/1.1\r\nHost: example.com\r\nAccept-Encoding: gzip, deflate\r\n\r\n";
+        let mut encoder = ZlibEncoder::with_dictionary(Vec::new(), &DeflateOptions::default(), dictionary);
+        encoder.write_all(request).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let header = read_zlib_header(&mut Cursor::new(&compressed)).unwrap();
+        assert_eq!(header.dict_id, Some(adler32(1, dictionary)));
+        assert_eq!(ZlibDecoder::new(Cursor::new(&compressed)).err().unwrap().to_string(), "need dictionary");
+        let err = ZlibDecoder::with_dictionary(Cursor::new(&compressed), b"other").err().unwrap();
+        assert_eq!(err.to_string(), "incorrect dictionary");
+
+        let mut output = Vec::new();
+        ZlibDecoder::with_dictionary(Cursor::new(&compressed), dictionary)
+            .unwrap()
+            .read_to_end(&mut output)
+            .unwrap();
+        assert_eq!(output, request);
+    }
+}
@@ -1,34 +1,131 @@
 use std::env;
-use std::io::{self};
+use std::ffi::OsStr;
+use std::fs;
+use std::io::{self, BufRead};
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
 use std::process;
+use std::time::{Duration, UNIX_EPOCH};
 
-mod compression;
-mod decompression;
-mod util;
+use my_gzip::compression;
+use my_gzip::compression::deflate::GzHeader;
+use my_gzip::compression::{CompressionLevel, DeflateOptions};
+use my_gzip::decompression;
+use my_gzip::decompression::Format;
+use my_gzip::util::file_io;
+
+// Exit codes, as in gzip.h
+const OK: i32 = 0;
+const ERROR: i32 = 1;
+const WARNING: i32 = 2;
+
+/// Command line settings (the `-d`, `-k`, `-n`, `-N`, `-r`, `-f`, `-S` and `-1`…`-9` globals of gzip.c).
+struct Options {
+    decompress: bool,
+    level: CompressionLevel,
+    /// `-n`/`-N`; when neither is given the name and timestamp are saved
+    /// on compression but not restored on decompression.
+    no_name: Option<bool>,
+    /// `--reproducible`: byte-identical output across runs and machines.
+    reproducible: bool,
+    /// `--raw`: bare deflate data, without the gzip header and trailer.
+    raw: bool,
+    /// `-k`: keep the input files instead of replacing them.
+    keep: bool,
+    recursive: bool,
+    /// `-f`: overwrite existing files. When decompressing, input in no
+    /// known format is copied to the output file, and the input removed,
+    /// where gzip only passes such data through with `--stdout`.
+    force: bool,
+    suffix: String,
+}
+
+impl Options {
+    fn no_name(&self) -> bool {
+        self.no_name.unwrap_or(self.decompress)
+    }
+}
 
 fn main() {
     let args: Vec<String> = env::args().collect();
+    let (options, files) = parse_args(&args[1..]);
 
-    if args.len() < 2 {
+    if files.is_empty() {
         try_help();
     }
 
-    let input_file = &args[1];
-    let output_file = format!("{}.gz", input_file);
-
     install_signal_handlers();
 
-    if let Err(e) = compress_file(input_file, &output_file) {
-        eprintln!("Error: {}", e);
-        remove_output_file(&output_file);
-        process::exit(1);
+    let mut exit_code = OK;
+    for file in &files {
+        treat_file(Path::new(file), &options, &mut exit_code);
     }
+    do_exit(exit_code);
+}
 
-    println!("File {} compressed to {}", input_file, output_file);
+fn parse_args(args: &[String]) -> (Options, Vec<String>) {
+    let mut options = Options {
+        decompress: false,
+        level: CompressionLevel::DEFAULT,
+        no_name: None,
+        reproducible: false,
+        raw: false,
+        keep: false,
+        recursive: false,
+        force: false,
+        suffix: String::from(".gz"),
+    };
+    let mut files = Vec::new();
+
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        match arg.as_str() {
+            "-d" | "--decompress" | "--uncompress" => options.decompress = true,
+            "--fast" => options.level = CompressionLevel::FAST,
+            "--best" => options.level = CompressionLevel::BEST,
+            "-1" | "-2" | "-3" | "-4" | "-5" | "-6" | "-7" | "-8" | "-9" => {
+                options.level = CompressionLevel::new(arg.as_bytes()[1] - b'0')
+                    .expect("levels 1 to 9 are valid");
+            }
+            "-n" | "--no-name" => options.no_name = Some(true),
+            "-N" | "--name" => options.no_name = Some(false),
+            "--reproducible" => options.reproducible = true,
+            "--raw" => options.raw = true,
+            "-k" | "--keep" => options.keep = true,
+            "-r" | "--recursive" => options.recursive = true,
+            "-f" | "--force" => options.force = true,
+            "-S" | "--suffix" => match iter.next() {
+                Some(suffix) => options.suffix = suffix.clone(),
+                None => try_help(),
+            },
+            "--" => {
+                files.extend(iter.by_ref().cloned());
+            }
+            _ => {
+                if let Some(suffix) = arg.strip_prefix("--suffix=") {
+                    options.suffix = suffix.to_string();
+                } else if let Some(suffix) = arg.strip_prefix("-S") {
+                    options.suffix = suffix.to_string();
+                } else if arg.starts_with('-') && arg.len() > 1 {
+                    try_help();
+                } else {
+                    files.push(arg.clone());
+                }
+            }
+        }
+    }
+
+    if options.suffix.is_empty() || options.suffix.contains('/') {
+        eprintln!("gzip: invalid suffix '{}'", options.suffix);
+        do_exit(ERROR);
+    }
+
+    (options, files)
 }
 
 fn try_help() -> ! {
-    eprintln!("Usage: gzip-rs <file>");
+    eprintln!("Usage: gzip-rs [-d] [-1..-9] [-n|-N] [--reproducible] [--raw] [-k] [-r] [-f] [-S suffix] <file>...");
     process::exit(1);
 }
 
@@ -41,9 +138,122 @@ fn install_signal_handlers() {
     }).expect("Error setting signal handler");
 }
 
-fn remove_output_file(filename: &str) {
+/// Records a per-file status; an error is never downgraded to a warning.
+fn set_exit_code(exit_code: &mut i32, code: i32) {
+    if *exit_code == OK || code == ERROR {
+        *exit_code = code;
+    }
+}
+
+/// Compresses a single command line operand, descending into it with `-r`.
+/// Problems are reported and recorded in `exit_code` so the remaining files
+/// are still processed.
+fn treat_file(path: &Path, options: &Options, exit_code: &mut i32) {
+    let name = path.display();
+
+    let mut metadata = match fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(e) => {
+            eprintln!("gzip: {}: {}", name, e);
+            set_exit_code(exit_code, ERROR);
+            return;
+        }
+    };
+
+    if metadata.file_type().is_symlink() {
+        if !options.force {
+            eprintln!("gzip: {} is a symbolic link -- ignored", name);
+            set_exit_code(exit_code, WARNING);
+            return;
+        }
+        metadata = match fs::metadata(path) {
+            Ok(metadata) => metadata,
+            Err(e) => {
+                eprintln!("gzip: {}: {}", name, e);
+                set_exit_code(exit_code, ERROR);
+                return;
+            }
+        };
+    }
+
+    if metadata.is_dir() {
+        if options.recursive {
+            treat_dir(path, options, exit_code);
+        } else {
+            eprintln!("gzip: {} is a directory -- ignored", name);
+            set_exit_code(exit_code, WARNING);
+        }
+        return;
+    }
+
+    if !metadata.is_file() {
+        eprintln!("gzip: {} is not a directory or a regular file - ignored", name);
+        set_exit_code(exit_code, WARNING);
+        return;
+    }
+
+    let result = if options.decompress {
+        let Some(suffix) = decompress_suffix(path, options) else {
+            if !options.recursive {
+                eprintln!("gzip: {}: unknown suffix -- ignored", name);
+                set_exit_code(exit_code, WARNING);
+            }
+            return;
+        };
+        decompress_file(path, suffix, options, exit_code)
+    } else {
+        if file_io::has_suffix(path, &options.suffix) {
+            // Like gzip, stay quiet about compressed files met while recursing.
+            if !options.recursive {
+                eprintln!("gzip: {} already has {} suffix -- unchanged", name, options.suffix);
+                set_exit_code(exit_code, WARNING);
+            }
+            return;
+        }
+        compress_file(path, options)
+    };
+
+    match result {
+        Ok(output_path) => {
+            println!(
+                "File {} {} to {}",
+                name,
+                if options.decompress { "decompressed" } else { "compressed" },
+                output_path.display()
+            );
+            // Like gzip, the output replaces the input unless -k is given
+            if !options.keep {
+                if let Err(e) = fs::remove_file(path) {
+                    eprintln!("gzip: {}: {}", name, e);
+                    set_exit_code(exit_code, WARNING);
+                }
+            }
+        }
+        Err(e) => {
+            eprintln!("gzip: {}: {}", name, e);
+            set_exit_code(exit_code, ERROR);
+        }
+    }
+}
+
+fn treat_dir(dir: &Path, options: &Options, exit_code: &mut i32) {
+    let entries = match file_io::list_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => {
+            eprintln!("gzip: {}: {}", dir.display(), e);
+            set_exit_code(exit_code, ERROR);
+            return;
+        }
+    };
+
+    for entry in entries {
+        treat_file(&entry, options, exit_code);
+    }
+}
+
+fn remove_output_file(filename: &Path) {
     if let Err(e) = std::fs::remove_file(filename) {
-        eprintln!("Warning: Unable to remove {}: {}", filename, e);
+        eprintln!("Warning: Unable to remove {}: {}", filename.display(), e);
     }
 }
 
@@ -51,9 +261,294 @@ fn do_exit(code: i32) -> ! {
     process::exit(code);
 }
 
-fn compress_file(input_file: &str, output_file: &str) -> io::Result<()> {
-    let mut input = std::fs::File::open(input_file)?;
-    let mut output = std::fs::File::create(output_file)?;
-    compression::deflate::deflate(&mut input, &mut output, Some(input_file));
-    Ok(()) // 将返回值转换为 Result<(), io::Error>
+/// Creates an output file that stays private until copy_stat gives it the
+/// input's mode. An existing file is only replaced with `-f`.
+fn create_output(output_path: &Path, options: &Options) -> io::Result<fs::File> {
+    let mut open_options = fs::OpenOptions::new();
+    open_options.write(true).mode(0o600);
+    if options.force {
+        open_options.create(true).truncate(true);
+    } else {
+        open_options.create_new(true);
+    }
+    open_options.open(output_path).map_err(|e| {
+        if e.kind() == io::ErrorKind::AlreadyExists {
+            io::Error::new(
+                e.kind(),
+                format!("{} already exists; not overwritten", output_path.display()),
+            )
+        } else {
+            e
+        }
+    })
+}
+
+fn compress_file(input_path: &Path, options: &Options) -> io::Result<PathBuf> {
+    let mut input = fs::File::open(input_path)?;
+    let metadata = input.metadata()?;
+
+    let mut output_name = input_path.as_os_str().to_owned();
+    output_name.push(&options.suffix);
+    let output_path = PathBuf::from(output_name);
+
+    // gzip stores the base name and the modification time unless -n is given
+    let header = if options.reproducible {
+        GzHeader::reproducible()
+    } else if options.no_name() {
+        GzHeader::default()
+    } else {
+        GzHeader {
+            filename: input_path.file_name().map(|name| name.as_bytes().to_vec()),
+            mtime: gzip_mtime(&metadata),
+            ..GzHeader::default()
+        }
+    };
+
+    let mut output = create_output(&output_path, options)?;
+    let deflate_options = DeflateOptions { level: options.level };
+    let result = if options.raw {
+        compression::deflate_raw(&mut input, &mut output, &deflate_options)
+    } else {
+        compression::deflate(&mut input, &mut output, &header, &deflate_options)
+    };
+    let result = result.and_then(|_| file_io::copy_stat(input_path, &metadata, &output));
+    if let Err(e) = result {
+        remove_output_file(&output_path);
+        return Err(e);
+    }
+    Ok(output_path)
+}
+
+/// The suffix to strip when decompressing `path`: the `-S` suffix, or the
+/// `.Z` of compress(1) and `.z` of pack(1) files.
+fn decompress_suffix<'a>(path: &Path, options: &'a Options) -> Option<&'a str> {
+    [options.suffix.as_str(), ".Z", ".z"].into_iter().find(|suffix| file_io::has_suffix(path, suffix))
+}
+
+fn decompress_file(input_path: &Path, suffix: &str, options: &Options, exit_code: &mut i32) -> io::Result<PathBuf> {
+    let input = fs::File::open(input_path)?;
+    let metadata = input.metadata()?;
+    let mut input = io::BufReader::new(input);
+    let output_path = strip_suffix(input_path, suffix);
+    if options.raw {
+        // No header: the output name always comes from the input name
+        return decode_to_file(input_path, &metadata, &output_path, options, |output| {
+            decompression::inflate(&mut input, output)
+        });
+    }
+    match decompression::detect_format(input.fill_buf()?) {
+        Format::Zlib => {
+            let result = decode_to_file(input_path, &metadata, &output_path, options, |output| {
+                io::copy(&mut decompression::ZlibDecoder::new(input)?, output).map(|_| ())
+            });
+            // Two bytes passing the header check may well be plain text:
+            // with -f, data that does not decode is copied as it is
+            return match result {
+                Err(e) if options.force && matches!(e.kind(), io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof) => {
+                    copy_to_file(input_path, &metadata, &output_path, options)
+                }
+                result => result,
+            };
+        }
+        Format::Lzw => {
+            return decode_to_file(input_path, &metadata, &output_path, options, |output| {
+                decompression::unlzw(&mut input, output)
+            });
+        }
+        Format::Pack => {
+            return decode_to_file(input_path, &metadata, &output_path, options, |output| {
+                decompression::unpack(&mut input, output)
+            });
+        }
+        Format::Lzh => {
+            return decode_to_file(input_path, &metadata, &output_path, options, |output| {
+                decompression::unlzh(&mut input, output)
+            });
+        }
+        Format::Zip => {
+            // Like gzip, only the first entry is extracted, under the input's name
+            return decode_to_file(input_path, &metadata, &output_path, options, |output| {
+                decompression::unzip(&mut input, output).map(|_| ())
+            });
+        }
+        Format::Plain if options.force => {
+            // Like zcat -f, data in no known format is copied through unchanged
+            drop(input);
+            return copy_to_file(input_path, &metadata, &output_path, options);
+        }
+        // Without -f, the gzip decoder reports what is wrong with the data
+        Format::Gzip | Format::Plain => {}
+    }
+
+    let mut decoder = decompression::GzDecoder::new(input)?;
+    let header = decoder.header().clone();
+
+    let output_path = match &header.filename {
+        // -N: restore the stored name, but never outside the input's directory
+        Some(stored) if !options.no_name() => {
+            let stored = Path::new(OsStr::from_bytes(stored));
+            match stored.file_name() {
+                Some(base) => input_path.with_file_name(base),
+                None => output_path,
+            }
+        }
+        _ => output_path,
+    };
+    if output_path == input_path {
+        // With -f, create_output would truncate the input before it is read
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("stored name {} is the input file -- not overwritten", output_path.display()),
+        ));
+    }
+
+    let mut output = create_output(&output_path, options)?;
+    let result = io::copy(&mut decoder, &mut output).and_then(|_| {
+        file_io::copy_stat(input_path, &metadata, &output)?;
+        if !options.no_name() && header.mtime != 0 {
+            output.set_modified(UNIX_EPOCH + Duration::from_secs(header.mtime as u64))?;
+        }
+        Ok(())
+    });
+    if let Err(e) = result {
+        remove_output_file(&output_path);
+        return Err(e);
+    }
+    if decoder.trailing_garbage() {
+        eprintln!("gzip: {}: decompression OK, trailing garbage ignored", input_path.display());
+        set_exit_code(exit_code, WARNING);
+    }
+    Ok(output_path)
+}
+
+/// Writes what `decode` produces to a new `output_path` with the input's
+/// attributes, for the formats without a name or time to restore.
+fn decode_to_file(
+    input_path: &Path,
+    metadata: &fs::Metadata,
+    output_path: &Path,
+    options: &Options,
+    decode: impl FnOnce(&mut fs::File) -> io::Result<()>,
+) -> io::Result<PathBuf> {
+    let mut output = create_output(output_path, options)?;
+    let result = decode(&mut output).and_then(|_| file_io::copy_stat(input_path, metadata, &output));
+    if let Err(e) = result {
+        remove_output_file(output_path);
+        return Err(e);
+    }
+    Ok(output_path.to_path_buf())
+}
+
+/// Copies the input as it is to `output_path`, with the input's attributes.
+fn copy_to_file(
+    input_path: &Path,
+    metadata: &fs::Metadata,
+    output_path: &Path,
+    options: &Options,
+) -> io::Result<PathBuf> {
+    let mut input = fs::File::open(input_path)?;
+    decode_to_file(input_path, metadata, output_path, options, |output| io::copy(&mut input, output).map(|_| ()))
+}
+
+/// Modification time as stored in the header; 0 if it does not fit in 32 bits.
+fn gzip_mtime(metadata: &fs::Metadata) -> u32 {
+    metadata
+        .modified()
+        .ok()
+        .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+        .and_then(|since_epoch| u32::try_from(since_epoch.as_secs()).ok())
+        .unwrap_or(0)
+}
+
+fn strip_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let bytes = path.as_os_str().as_bytes();
+    PathBuf::from(OsStr::from_bytes(&bytes[..bytes.len() - suffix.len()]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn options(args: &[&str]) -> Options {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        parse_args(&args).0
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("gzip_main_{}_{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("Unable to create test directory");
+        dir
+    }
+
+    fn gunzip(path: &Path) -> Vec<u8> {
+        let mut output = Vec::new();
+        io::copy(&mut decompression::GzDecoder::new(fs::File::open(path).unwrap()).unwrap(), &mut output).unwrap();
+        output
+    }
+
+    #[test]
+    fn test_recursive_compression_replaces_files() {
+        let dir = test_dir("recursive");
+        fs::create_dir(dir.join("sub")).unwrap();
+        fs::write(dir.join("a.txt"), b"first file\n").unwrap();
+        fs::write(dir.join("sub/b.txt"), b"second file\n").unwrap();
+        fs::write(dir.join("sub/c.gz"), b"already compressed").unwrap();
+
+        let mut exit_code = OK;
+        treat_file(&dir, &options(&["-r"]), &mut exit_code);
+        assert_eq!(exit_code, OK);
+        assert!(!dir.join("a.txt").exists() && !dir.join("sub/b.txt").exists());
+        assert_eq!(gunzip(&dir.join("a.txt.gz")), b"first file\n");
+        assert_eq!(gunzip(&dir.join("sub/b.txt.gz")), b"second file\n");
+        assert_eq!(fs::read(dir.join("sub/c.gz")).unwrap(), b"already compressed");
+        assert!(!dir.join("sub/c.gz.gz").exists());
+
+        // -d -r puts the originals back; with -k the inputs stay
+        treat_file(&dir, &options(&["-d", "-r", "-k"]), &mut exit_code);
+        assert_eq!(exit_code, ERROR); // sub/c.gz is not in gzip format
+        assert_eq!(fs::read(dir.join("a.txt")).unwrap(), b"first file\n");
+        assert!(dir.join("a.txt.gz").exists() && dir.join("sub/b.txt.gz").exists());
+
+        fs::remove_dir_all(dir).expect("Unable to remove test directory");
+    }
+
+    #[test]
+    fn test_force_copies_text_that_looks_like_zlib() {
+        let dir = test_dir("zlib_like");
+        // "Hj" passes the zlib header check: method 8, and 0x486a is a
+        // multiple of 31
+        let text = b"Hj, this is plain text\n";
+        assert_eq!(decompression::detect_format(text), Format::Zlib);
+        fs::write(dir.join("notes.gz"), text).unwrap();
+
+        let mut exit_code = OK;
+        treat_file(&dir.join("notes.gz"), &options(&["-d"]), &mut exit_code);
+        assert_eq!(exit_code, ERROR);
+        assert!(!dir.join("notes").exists());
+
+        let mut exit_code = OK;
+        treat_file(&dir.join("notes.gz"), &options(&["-d", "-f"]), &mut exit_code);
+        assert_eq!(exit_code, OK);
+        assert_eq!(fs::read(dir.join("notes")).unwrap(), text);
+
+        fs::remove_dir_all(dir).expect("Unable to remove test directory");
+    }
+
+    #[test]
+    fn test_stored_name_never_overwrites_the_input() {
+        let dir = test_dir("stored_name");
+        let input_path = dir.join("x.gz");
+        let header = GzHeader { filename: Some(b"x.gz".to_vec()), ..GzHeader::default() };
+        let mut compressed = Vec::new();
+        compression::deflate(&mut &b"data"[..], &mut compressed, &header, &DeflateOptions::default()).unwrap();
+        fs::write(&input_path, &compressed).unwrap();
+
+        let mut exit_code = OK;
+        treat_file(&input_path, &options(&["-d", "-N", "-f"]), &mut exit_code);
+        assert_eq!(exit_code, ERROR);
+        assert_eq!(fs::read(&input_path).unwrap(), compressed);
+
+        fs::remove_dir_all(dir).expect("Unable to remove test directory");
+    }
 }
@@ -0,0 +1,110 @@
+const BASE: u32 = 65521; // largest prime smaller than 65536
+// NMAX is the largest n such that 255n(n+1)/2 + (n+1)(BASE-1) <= 2^32-1:
+// the number of bytes that can be summed before the modulo is needed
+const NMAX: usize = 5552;
+
+/// Updates a running Adler-32 checksum (RFC 1950) with `buffer`; the
+/// checksum of no data is 1.
+pub fn adler32(adler: u32, buffer: &[u8]) -> u32 {
+    let mut a = adler & 0xffff;
+    let mut b = adler >> 16;
+
+    // 每 NMAX 字节才取一次模
+    for batch in buffer.chunks(NMAX) {
+        for &byte in batch {
+            a += byte as u32;
+            b += a;
+        }
+        a %= BASE;
+        b %= BASE;
+    }
+    (b << 16) | a
+}
+
+/// Returns the Adler-32 of two pieces of data put together, from the
+/// checksums of each and the length of the second.
+pub fn adler32_combine(adler1: u32, adler2: u32, len2: u64) -> u32 {
+    let rem = (len2 % BASE as u64) as u32;
+    let mut sum1 = adler1 & 0xffff;
+    let mut sum2 = (rem * sum1) % BASE;
+    sum1 += (adler2 & 0xffff) + BASE - 1;
+    sum2 += (adler1 >> 16) + (adler2 >> 16) + BASE - rem;
+    if sum1 >= BASE {
+        sum1 -= BASE;
+    }
+    if sum1 >= BASE {
+        sum1 -= BASE;
+    }
+    if sum2 >= BASE << 1 {
+        sum2 -= BASE << 1;
+    }
+    if sum2 >= BASE {
+        sum2 -= BASE;
+    }
+    (sum2 << 16) | sum1
+}
+
+/// Streaming Adler-32 hasher.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Adler32 {
+    adler: u32,
+}
+
+impl Default for Adler32 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Adler32 {
+    pub fn new() -> Self {
+        Adler32 { adler: 1 }
+    }
+
+    /// Continues from a checksum computed earlier.
+    pub fn from_checksum(adler: u32) -> Self {
+        Adler32 { adler }
+    }
+
+    pub fn update(&mut self, buffer: &[u8]) {
+        self.adler = adler32(self.adler, buffer);
+    }
+
+    /// The checksum of the data given so far.
+    pub fn checksum(&self) -> u32 {
+        self.adler
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_adler32_vectors() {
+        assert_eq!(adler32(1, b""), 1);
+        assert_eq!(adler32(1, b"a"), 0x0062_0062);
+        assert_eq!(adler32(1, b"abc"), 0x024d_0127);
+        assert_eq!(adler32(1, b"Wikipedia"), 0x11e6_0398);
+        assert_eq!(adler32(1, b"message digest"), 0x2975_0586);
+        // Long enough to need the modulo inside the batches
+        assert_eq!(adler32(1, &[0xff; 100_000]), 0x149a_302c);
+    }
+
+    #[test]
+    fn test_hasher_and_combine() {
+        let data: Vec<u8> = (0..20_000u32).map(|i| (i * 31 % 251) as u8).collect();
+        let whole = adler32(1, &data);
+
+        for split in [0, 1, 16, 5552, 5553, 19_999, 20_000] {
+            let mut hasher = Adler32::new();
+            hasher.update(&data[..split]);
+            hasher.update(&data[split..]);
+            assert_eq!(hasher.checksum(), whole);
+
+            let (first, second) = data.split_at(split);
+            let combined = adler32_combine(adler32(1, first), adler32(1, second), second.len() as u64);
+            assert_eq!(combined, whole, "split {}", split);
+        }
+    }
+}
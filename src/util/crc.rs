@@ -2,7 +2,7 @@ const CRC32_POLYNOMIAL: u32 = 0xEDB88320;
 lazy_static::lazy_static! {
     pub static ref CRC32_TABLE: [u32; 256] = {
         let mut table = [0u32; 256];
-        for i in 0..256 {
+        for (i, entry) in table.iter_mut().enumerate() {
             let mut crc = i as u32;
             for _ in 0..8 {
                 if crc & 1 != 0 {
@@ -11,7 +11,7 @@ lazy_static::lazy_static! {
                     crc >>= 1;
                 }
             }
-            table[i] = crc;
+            *entry = crc;
         }
         table
     };
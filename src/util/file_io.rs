@@ -1,11 +1,12 @@
-use std::fs::{File, OpenOptions};
+use std::fs::{self, File, FileTimes, OpenOptions};
 use std::io::{self, Read, Write};
-use std::path::Path;
+use std::os::unix::fs::{fchown, MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
 
 /// 从输入文件复制数据到输出文件
 pub fn copy(input_path: &Path, output_path: &Path) -> io::Result<()> {
     let mut input = File::open(input_path)?;
-    let mut output = OpenOptions::new().write(true).create(true).open(output_path)?;
+    let mut output = OpenOptions::new().write(true).create(true).truncate(true).open(output_path)?;
 
     let mut buffer = [0u8; 8192]; // 缓冲区大小，类似于 INBUFSIZ
     loop {
@@ -19,6 +20,119 @@ pub fn copy(input_path: &Path, output_path: &Path) -> io::Result<()> {
     Ok(())
 }
 
+/// 将输入文件的属性复制到输出文件（对应 gzip 的 copy_stat）：
+/// 扩展属性与 ACL、以 root 身份运行时的属主和属组、权限位，
+/// 以及纳秒精度的访问和修改时间。
+/// 与 gzip 一样通过仍然打开的输出文件修改属性，这样即使权限位
+/// 是只读的（如 0444），之后仍可以设置时间戳。
+/// `metadata` 应在打开输入文件时取得（对应 gzip 的 istat），
+/// 读取输入之后再取，访问时间就已经变成了读取的时间。
+pub fn copy_stat(input_path: &Path, metadata: &fs::Metadata, output: &File) -> io::Result<()> {
+
+    // 只有 root 才能任意修改属主，普通用户保留默认属主
+    if unsafe { libc::geteuid() } == 0 {
+        fchown(output, Some(metadata.uid()), Some(metadata.gid()))?;
+    }
+
+    // ACL 以 system.posix_acl_* 扩展属性的形式保存，随扩展属性一起复制
+    copy_xattrs(input_path, output)?;
+
+    // chown 可能清除 setuid/setgid 位，因此权限要在其后设置
+    output.set_permissions(fs::Permissions::from_mode(metadata.mode() & 0o7777))?;
+
+    // 最后设置时间戳，避免前面的操作再次修改它们
+    let times = FileTimes::new()
+        .set_accessed(metadata.accessed()?)
+        .set_modified(metadata.modified()?);
+    output.set_times(times)?;
+
+    Ok(())
+}
+
+#[cfg(target_os = "linux")]
+fn copy_xattrs(input_path: &Path, output: &File) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+    use std::os::unix::io::AsRawFd;
+
+    let input = CString::new(input_path.as_os_str().as_bytes())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+
+    // 目标文件系统不支持或无权设置的属性（如 trusted.*）直接跳过
+    let ignorable = |err: &io::Error| {
+        matches!(err.raw_os_error(), Some(libc::ENOTSUP) | Some(libc::EPERM) | Some(libc::EACCES))
+    };
+
+    let names = match read_xattr_buffer(|buf, size| unsafe {
+        libc::listxattr(input.as_ptr(), buf as *mut libc::c_char, size)
+    }) {
+        Ok(names) => names,
+        Err(ref err) if ignorable(err) => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    for name in names.split(|&b| b == 0).filter(|name| !name.is_empty()) {
+        let name = CString::new(name).expect("xattr names contain no NUL bytes");
+        let value = match read_xattr_buffer(|buf, size| unsafe {
+            libc::getxattr(input.as_ptr(), name.as_ptr(), buf as *mut libc::c_void, size)
+        }) {
+            Ok(value) => value,
+            Err(ref err) if ignorable(err) => continue,
+            Err(err) => return Err(err),
+        };
+
+        let ret = unsafe {
+            libc::fsetxattr(
+                output.as_raw_fd(),
+                name.as_ptr(),
+                value.as_ptr() as *const libc::c_void,
+                value.len(),
+                0,
+            )
+        };
+        if ret != 0 {
+            let err = io::Error::last_os_error();
+            if !ignorable(&err) {
+                return Err(err);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(not(target_os = "linux"))]
+fn copy_xattrs(_input_path: &Path, _output: &File) -> io::Result<()> {
+    Ok(())
+}
+
+/// 调用 listxattr/getxattr 一类的函数：先查询所需大小再读取，
+/// 如果属性在两次调用之间变大（ERANGE）就重试
+#[cfg(target_os = "linux")]
+fn read_xattr_buffer<F>(mut call: F) -> io::Result<Vec<u8>>
+where
+    F: FnMut(*mut u8, usize) -> isize,
+{
+    loop {
+        let size = call(std::ptr::null_mut(), 0);
+        if size < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        let mut buffer = vec![0u8; size as usize];
+        let read = call(buffer.as_mut_ptr(), buffer.len());
+        if read >= 0 {
+            buffer.truncate(read as usize);
+            return Ok(buffer);
+        }
+
+        let err = io::Error::last_os_error();
+        if err.raw_os_error() != Some(libc::ERANGE) {
+            return Err(err);
+        }
+    }
+}
+
 /// 修改文件名，将所有的点替换为下划线，除了最后一个点
 pub fn make_simple_name(name: &mut String) {
     if let Some(dot_pos) = name.rfind('.') {
@@ -35,11 +149,28 @@ pub fn make_simple_name(name: &mut String) {
     }
 }
 
+/// 判断文件名是否已经带有压缩后缀（对应 gzip 的 get_suffix）
+pub fn has_suffix(path: &Path, suffix: &str) -> bool {
+    match path.file_name() {
+        Some(name) => name.to_string_lossy().ends_with(suffix),
+        None => false,
+    }
+}
+
+/// 列出目录中的所有条目，按名称排序以保证处理顺序稳定
+pub fn list_dir(dir: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut entries = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        entries.push(entry?.path());
+    }
+    entries.sort();
+    Ok(entries)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::fs;
-    use std::path::PathBuf;
+    use std::os::unix::fs::OpenOptionsExt;
 
     #[test]
     fn test_copy() {
@@ -56,10 +187,91 @@ mod tests {
         fs::remove_file(output_path).expect("Unable to remove test output file");
     }
 
+    #[test]
+    fn test_copy_stat() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let dir = std::env::temp_dir().join(format!("gzip_copy_stat_{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("Unable to create test directory");
+        let input_path = dir.join("input");
+        let output_path = dir.join("output");
+        fs::write(&input_path, b"input").expect("Unable to write test file");
+        fs::write(&output_path, b"output").expect("Unable to write test file");
+
+        fs::set_permissions(&input_path, fs::Permissions::from_mode(0o640)).unwrap();
+        let mtime = UNIX_EPOCH + Duration::new(1_000_000_000, 123_456_789);
+        let atime = UNIX_EPOCH + Duration::new(1_100_000_000, 987_654_321);
+        File::options().write(true).open(&input_path).unwrap()
+            .set_times(FileTimes::new().set_accessed(atime).set_modified(mtime))
+            .unwrap();
+
+        let input_metadata = fs::metadata(&input_path).unwrap();
+        // 读取输入会改变它的访问时间，恢复的应是打开时的时间
+        File::options().write(true).open(&input_path).unwrap()
+            .set_times(FileTimes::new().set_accessed(UNIX_EPOCH + Duration::new(1_200_000_000, 0)))
+            .unwrap();
+        let output = File::options().write(true).open(&output_path).unwrap();
+        copy_stat(&input_path, &input_metadata, &output).expect("copy_stat failed");
+
+        let metadata = fs::metadata(&output_path).unwrap();
+        assert_eq!(metadata.mode() & 0o7777, 0o640);
+        assert_eq!(metadata.modified().unwrap(), mtime);
+        assert_eq!(metadata.accessed().unwrap(), atime);
+
+        fs::remove_dir_all(dir).expect("Unable to remove test directory");
+    }
+
+    #[test]
+    fn test_copy_stat_read_only_input() {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        let dir = std::env::temp_dir().join(format!("gzip_copy_stat_ro_{}", std::process::id()));
+        fs::create_dir_all(&dir).expect("Unable to create test directory");
+        let input_path = dir.join("input");
+        let output_path = dir.join("output");
+        fs::write(&input_path, b"input").expect("Unable to write test file");
+        let mtime = UNIX_EPOCH + Duration::new(1_000_000_000, 0);
+        File::options().write(true).open(&input_path).unwrap()
+            .set_times(FileTimes::new().set_modified(mtime))
+            .unwrap();
+        fs::set_permissions(&input_path, fs::Permissions::from_mode(0o444)).unwrap();
+
+        // 输出文件像 gzip 创建的那样：0600，仍然打开着
+        let output = OpenOptions::new().write(true).create_new(true).mode(0o600).open(&output_path).unwrap();
+        copy_stat(&input_path, &fs::metadata(&input_path).unwrap(), &output).expect("copy_stat failed");
+        drop(output);
+
+        let metadata = fs::metadata(&output_path).unwrap();
+        assert_eq!(metadata.mode() & 0o7777, 0o444);
+        assert_eq!(metadata.modified().unwrap(), mtime);
+
+        fs::remove_dir_all(dir).expect("Unable to remove test directory");
+    }
+
     #[test]
     fn test_make_simple_name() {
         let mut filename = String::from("example.test.file");
         make_simple_name(&mut filename);
         assert_eq!(filename, "example_test.file");
     }
+
+    #[test]
+    fn test_has_suffix() {
+        assert!(has_suffix(Path::new("logs/app.log.gz"), ".gz"));
+        assert!(!has_suffix(Path::new("logs/app.log"), ".gz"));
+        assert!(!has_suffix(Path::new("logs.gz/app.log"), ".gz"));
+    }
+
+    #[test]
+    fn test_list_dir() {
+        let dir = std::env::temp_dir().join(format!("gzip_list_dir_{}", std::process::id()));
+        fs::create_dir_all(dir.join("sub")).expect("Unable to create test directory");
+        fs::write(dir.join("b.txt"), b"b").expect("Unable to write test file");
+        fs::write(dir.join("a.txt"), b"a").expect("Unable to write test file");
+
+        let entries = list_dir(&dir).expect("Listing failed");
+        assert_eq!(entries, vec![dir.join("a.txt"), dir.join("b.txt"), dir.join("sub")]);
+
+        fs::remove_dir_all(dir).expect("Unable to remove test directory");
+    }
 }
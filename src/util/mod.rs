@@ -1,5 +1,6 @@
 // src/util/mod.rs
 
+pub mod adler32;
 pub mod crc;
 pub mod file_io;
 pub mod error;